@@ -0,0 +1,15 @@
+//! Helpers shared by the benchmarks.
+
+/// Linear congruential generator, so the generated inputs are the same on
+/// every run. The same constants as the one the unit tests use.
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % n
+    }
+}
@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reg::nfa::{dot, literal, star_fresh, times, union, NFA};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+use common::Lcg;
+
+/// Tracks the bytes currently allocated, to compare the footprint of the two
+/// representations.
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn footprint<T>(build: impl FnOnce() -> T) -> (T, usize) {
+    let before = LIVE.load(Ordering::Relaxed);
+    let value = build();
+    (value, LIVE.load(Ordering::Relaxed) - before)
+}
+
+/// Deterministic pseudo-random lowercase words.
+fn words(count: usize, len: usize) -> Vec<String> {
+    let mut rng = Lcg(0x5eed);
+    (0..count)
+        .map(|_| {
+            (0..len)
+                .map(|_| (b'a' + rng.below(26) as u8) as char)
+                .collect()
+        })
+        .collect()
+}
+
+fn large_automaton(c: &mut Criterion) {
+    let dictionary = words(1000, 6);
+    let parts: Vec<NFA> = dictionary.iter().map(|word| literal(word)).collect();
+    // any text ending in one of the words
+    let nfa = times(&star_fresh(&dot()), &union(&parts));
+    let (nfa, hashed) = footprint(|| nfa.clone());
+    let (compiled, compact) = footprint(|| nfa.compile());
+    println!(
+        "{} states: {} bytes hashed, {} bytes compiled",
+        nfa.num_states(),
+        hashed,
+        compact
+    );
+
+    let input = words(2_000, 10).concat() + &dictionary[0];
+    let mut group = c.benchmark_group("large_automaton_20k");
+    group.bench_function("hashmap", |b| b.iter(|| nfa.is_match(black_box(&input))));
+    group.bench_function("compiled", |b| {
+        b.iter(|| compiled.is_match(black_box(&input)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, large_automaton);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reg::nfa::{concat, times, unit, ExtendedChar, NFA};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts allocations, to compare how much composing the automata copies.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn parts() -> Vec<NFA> {
+    (0..1000)
+        .map(|i| unit(ExtendedChar::Char(char::from(b'a' + (i % 26) as u8))))
+        .collect()
+}
+
+fn nested(parts: &[NFA]) -> NFA {
+    parts[1..]
+        .iter()
+        .fold(parts[0].clone(), |nfa, part| times(&nfa, part))
+}
+
+fn report(name: &str, build: impl FnOnce() -> NFA) {
+    let (allocations, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    black_box(build());
+    println!(
+        "{}: {} allocations, {} bytes",
+        name,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        BYTES.load(Ordering::Relaxed) - bytes
+    );
+}
+
+fn compose_1000(c: &mut Criterion) {
+    let parts = parts();
+    report("nested times", || nested(&parts));
+    report("concat", || concat(&parts));
+
+    let mut group = c.benchmark_group("compose_1000");
+    group.bench_function("nested_times", |b| b.iter(|| nested(black_box(&parts))));
+    group.bench_function("concat", |b| b.iter(|| concat(black_box(&parts))));
+    group.finish();
+}
+
+criterion_group!(benches, compose_1000);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reg::parser::parse;
+
+fn match_1mb(c: &mut Criterion) {
+    let dfa = parse("(ab|c)*d").unwrap().to_dfa();
+    let table = dfa.to_table();
+    let input = "abc".repeat((1 << 20) / 3) + "d";
+
+    let mut group = c.benchmark_group("match_1mb");
+    group.bench_function("hashmap", |b| b.iter(|| dfa.is_match(black_box(&input))));
+    group.bench_function("table", |b| b.iter(|| table.is_match(black_box(&input))));
+    group.finish();
+}
+
+criterion_group!(benches, match_1mb);
+criterion_main!(benches);
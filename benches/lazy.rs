@@ -0,0 +1,35 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reg::lazy::LazyRegex;
+use reg::parser::parse;
+
+fn repeated_inputs(c: &mut Criterion) {
+    let pattern = "(a|b)*a(a|b)(a|b)(a|b)(a|b)(a|b)";
+    let nfa = parse(pattern).unwrap();
+    let lazy = LazyRegex::new(pattern).unwrap();
+    // many short inputs over the same few prefixes
+    let inputs: Vec<String> = (0..1000)
+        .map(|i| format!("{}{}", "abbab".repeat(10), ["a", "b", "ab", "ba"][i % 4]))
+        .collect();
+
+    let mut group = c.benchmark_group("repeated_1000");
+    group.bench_function("uncached", |b| {
+        b.iter(|| {
+            black_box(&inputs)
+                .iter()
+                .filter(|input| nfa.is_match(input))
+                .count()
+        })
+    });
+    group.bench_function("lazy_dfa", |b| {
+        b.iter(|| {
+            black_box(&inputs)
+                .iter()
+                .filter(|input| lazy.is_match(input))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, repeated_inputs);
+criterion_main!(benches);
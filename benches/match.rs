@@ -0,0 +1,44 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use reg::nfa::{literal, star_fresh, times, NFA};
+use reg::parser::parse;
+
+const LENGTHS: [usize; 3] = [100, 10_000, 1_000_000];
+
+fn bench_inputs(c: &mut Criterion, name: &str, nfa: &NFA, input: impl Fn(usize) -> String) {
+    let mut group = c.benchmark_group(name);
+    for len in LENGTHS {
+        let input = input(len);
+        group.bench_with_input(BenchmarkId::from_parameter(len), &input, |b, input| {
+            b.iter(|| nfa.is_match(black_box(input)))
+        });
+    }
+    group.finish();
+}
+
+fn alternation_star(c: &mut Criterion) {
+    let nfa = parse("(a|b)*c").unwrap();
+    bench_inputs(c, "alternation_star", &nfa, |len| {
+        "ab".repeat(len / 2) + "c"
+    });
+}
+
+fn long_literal(c: &mut Criterion) {
+    // the literal is as long as the input, so every character is a new node
+    for len in [100, 10_000] {
+        let text = "abcdefghij".repeat(len / 10);
+        let nfa = literal(&text);
+        c.bench_function(&format!("long_literal/{}", len), |b| {
+            b.iter(|| nfa.is_match(black_box(&text)))
+        });
+    }
+}
+
+fn nested_stars(c: &mut Criterion) {
+    // ((((a*)*)*)*)*b: many epsilon paths lead to the same nodes
+    let nfa = (0..5).fold(parse("a").unwrap(), |nfa, _| star_fresh(&nfa));
+    let nfa = times(&nfa, &literal("b"));
+    bench_inputs(c, "nested_stars", &nfa, |len| "a".repeat(len - 1) + "b");
+}
+
+criterion_group!(benches, alternation_star, long_literal, nested_stars);
+criterion_main!(benches);
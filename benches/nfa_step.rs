@@ -0,0 +1,24 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reg::parser::parse;
+
+fn match_100k(c: &mut Criterion) {
+    // keeps several nodes active at once: the sixth character from the end is 'a'
+    let nfa = parse("(a|b)*a(a|b)(a|b)(a|b)(a|b)(a|b)").unwrap();
+    let input = "abbab".repeat(20_000);
+
+    let mut group = c.benchmark_group("nfa_100k");
+    group.bench_function("is_match", |b| b.iter(|| nfa.is_match(black_box(&input))));
+    group.bench_function("match_state", |b| {
+        b.iter(|| {
+            let mut state = nfa.start_state();
+            for ch in black_box(&input).chars() {
+                state.step(ch);
+            }
+            state.is_accepting()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, match_100k);
+criterion_main!(benches);
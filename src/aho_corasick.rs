@@ -0,0 +1,147 @@
+//! Searching for many fixed words at once.
+//!
+//! The trie built by `nfa::from_strings` is extended with failure links: on a
+//! character the trie can't follow, the search falls back to the longest
+//! proper suffix of the current path that is also a prefix of some word, so
+//! the input is scanned once, in time linear in its length.
+
+use crate::nfa::node::Node;
+use crate::nfa::{from_strings, ExtendedChar};
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    // trie edges of each node
+    goto: Vec<HashMap<char, usize>>,
+    fail: Vec<usize>,
+    // length in characters of the longest word ending at each node,
+    // following failure links
+    output: Vec<Option<usize>>,
+}
+
+impl AhoCorasick {
+    pub fn new(words: &[&str]) -> AhoCorasick {
+        let trie = from_strings(words);
+        let mut goto = vec![HashMap::new(); trie.num_states()];
+        for (Node(from), ec, Node(to)) in trie.transitions() {
+            if let ExtendedChar::Char(ch) = ec {
+                goto[from].insert(ch, to);
+            }
+        }
+
+        let mut fail = vec![0; goto.len()];
+        let mut depth = vec![0; goto.len()];
+        let mut output = vec![None; goto.len()];
+        output[0] = trie.is_final(Node(0)).then_some(0);
+        // breadth-first, so the failure target of a node is always done
+        let mut pending: VecDeque<usize> = goto[0].values().copied().collect();
+        for &child in pending.iter() {
+            depth[child] = 1;
+        }
+        while let Some(node) = pending.pop_front() {
+            output[node] = if trie.is_final(Node(node)) {
+                Some(depth[node])
+            } else {
+                output[fail[node]]
+            };
+            let children: Vec<(char, usize)> =
+                goto[node].iter().map(|(&ch, &child)| (ch, child)).collect();
+            for (ch, child) in children {
+                depth[child] = depth[node] + 1;
+                if node != 0 {
+                    fail[child] = Self::follow(&goto, &fail, fail[node], ch);
+                }
+                pending.push_back(child);
+            }
+        }
+        AhoCorasick { goto, fail, output }
+    }
+
+    /// The byte range of the occurrence of a word that ends first in `s`,
+    /// the longest one if several end at the same place. Note that a word
+    /// ending later may start earlier: in `"abcd"` with the words `"abcd"`
+    /// and `"bc"`, `"bc"` is found.
+    pub fn find(&self, s: &str) -> Option<(usize, usize)> {
+        if self.output[0].is_some() {
+            return Some((0, 0));
+        }
+        // byte offset of every character seen, to turn a word length in
+        // characters back into a start offset
+        let mut starts = Vec::new();
+        let mut node = 0;
+        for (i, ch) in s.char_indices() {
+            starts.push(i);
+            node = Self::follow(&self.goto, &self.fail, node, ch);
+            if let Some(len) = self.output[node] {
+                return Some((starts[starts.len() - len], i + ch.len_utf8()));
+            }
+        }
+        None
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.goto.len()
+    }
+
+    /// The node reached from `node` on `ch`, falling back along failure
+    /// links until some node has an edge on `ch`, or the root.
+    fn follow(goto: &[HashMap<char, usize>], fail: &[usize], mut node: usize, ch: char) -> usize {
+        loop {
+            if let Some(&next) = goto[node].get(&ch) {
+                return next;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = fail[node];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aho_corasick::*;
+    use crate::nfa::{contains, literal, union, NFA};
+
+    #[test]
+    pub fn test_find() {
+        let ac = AhoCorasick::new(&["he", "she", "his", "hers"]);
+        assert_eq!(ac.find("ushers"), Some((1, 4)));
+        assert_eq!(ac.find("ahishers"), Some((1, 4)));
+        assert_eq!(ac.find("hxe"), None);
+        assert_eq!(ac.find(""), None);
+        assert_eq!(AhoCorasick::new(&["abcd", "bc"]).find("abcd"), Some((1, 3)));
+        assert_eq!(AhoCorasick::new(&["本"]).find("日本語"), Some((3, 6)));
+        assert_eq!(AhoCorasick::new(&["", "a"]).find("ba"), Some((0, 0)));
+        assert_eq!(AhoCorasick::new(&[]).find("ba"), None);
+    }
+
+    #[test]
+    pub fn test_find_agrees_with_contains() {
+        let words = ["ab", "bab", "bb", "aaa"];
+        let ac = AhoCorasick::new(&words);
+        let parts: Vec<NFA> = words.iter().map(|word| literal(word)).collect();
+        let any_word = contains(&union(&parts), &['a', 'b']);
+        let mut inputs = vec![String::new()];
+        for _ in 0..6 {
+            let longer: Vec<String> = inputs
+                .iter()
+                .flat_map(|s| [format!("{}a", s), format!("{}b", s)])
+                .collect();
+            inputs.extend(longer);
+        }
+        for input in inputs.iter() {
+            assert_eq!(
+                ac.find(input).is_some(),
+                any_word.is_match(input),
+                "{:?}",
+                input
+            );
+            if let Some((start, end)) = ac.find(input) {
+                assert!(words.contains(&&input[start..end]));
+                // no word ends any earlier
+                assert!(!any_word.is_match(&input[..end - 1]));
+            }
+        }
+    }
+}
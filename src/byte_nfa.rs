@@ -0,0 +1,209 @@
+//! A byte-oriented counterpart of `NFA` for matching arbitrary binary data,
+//! such as network protocols, without any UTF-8 assumptions.
+//!
+//! The combinators mirror the ones in `nfa` and use the same epsilon-based
+//! constructions, with `star` going through a fresh start node like
+//! `nfa::star`.
+//!
+//! The closure, stepping and combinators are written out again rather than
+//! shared with `NFA` behind an alphabet type parameter. Little of `NFA`'s
+//! versions would carry over unchanged: its steps follow `Wildcard` edges
+//! alongside exact ones, and the class and case-insensitive constructors,
+//! the DFA and the serialized format are all written for `char` labels. A
+//! parameter would have to appear in every signature of the public `nfa`
+//! API, all for the few lines of closure and renumbering that bytes and
+//! characters have in common.
+
+use crate::nfa::node::Node;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteNFA {
+    pub(crate) states: usize,
+    pub(crate) starting: HashSet<Node>,
+    pub(crate) delta: HashMap<(Node, u8), HashSet<Node>>,
+    pub(crate) epsilon: HashMap<Node, HashSet<Node>>,
+    pub(crate) finished: HashSet<Node>,
+}
+
+impl ByteNFA {
+    pub fn is_match(&self, bytes: &[u8]) -> bool {
+        let mut nodes = self.epsilon_closure(&self.starting);
+        for &byte in bytes {
+            nodes = self.step_set(&nodes, byte);
+        }
+        !nodes.is_disjoint(&self.finished)
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.states
+    }
+
+    pub(crate) fn epsilon_closure(&self, nodes: &HashSet<Node>) -> HashSet<Node> {
+        let mut closure = nodes.clone();
+        let mut pending: Vec<Node> = nodes.iter().copied().collect();
+        while let Some(node) = pending.pop() {
+            for &next in self.epsilon.get(&node).into_iter().flatten() {
+                if closure.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        closure
+    }
+
+    pub(crate) fn step_set(&self, nodes: &HashSet<Node>, byte: u8) -> HashSet<Node> {
+        let mut new_nodes = HashSet::new();
+        for &node in nodes.iter() {
+            if let Some(set) = self.delta.get(&(node, byte)) {
+                new_nodes.extend(set.iter().copied());
+            }
+        }
+        self.epsilon_closure(&new_nodes)
+    }
+
+    /// Copies of `delta` and `epsilon` with every node moved up by `offset`.
+    fn shifted(&self, offset: usize) -> ShiftedEdges {
+        let shift = |&Node(n): &Node| Node(n + offset);
+        let delta = self
+            .delta
+            .iter()
+            .map(|(&(node, byte), set)| ((shift(&node), byte), set.iter().map(shift).collect()))
+            .collect();
+        let epsilon = self
+            .epsilon
+            .iter()
+            .map(|(node, set)| (shift(node), set.iter().map(shift).collect()))
+            .collect();
+        (delta, epsilon)
+    }
+}
+
+type ShiftedEdges = (
+    HashMap<(Node, u8), HashSet<Node>>,
+    HashMap<Node, HashSet<Node>>,
+);
+
+pub fn unit_byte(byte: u8) -> ByteNFA {
+    ByteNFA {
+        states: 2,
+        starting: [Node(0)].into(),
+        delta: [((Node(0), byte), [Node(1)].into())].into(),
+        epsilon: [].into(),
+        finished: [Node(1)].into(),
+    }
+}
+
+pub fn empty() -> ByteNFA {
+    ByteNFA {
+        states: 1,
+        starting: [Node(0)].into(),
+        delta: [].into(),
+        epsilon: [].into(),
+        finished: [Node(0)].into(),
+    }
+}
+
+pub fn plus(first: &ByteNFA, second: &ByteNFA) -> ByteNFA {
+    let increase = |&Node(n): &Node| Node(n + first.states);
+    let (second_delta, second_epsilon) = second.shifted(first.states);
+    let mut delta = first.delta.clone();
+    delta.extend(second_delta);
+    let mut epsilon = first.epsilon.clone();
+    epsilon.extend(second_epsilon);
+
+    ByteNFA {
+        states: first.states + second.states,
+        starting: first
+            .starting
+            .iter()
+            .copied()
+            .chain(second.starting.iter().map(increase))
+            .collect(),
+        delta,
+        epsilon,
+        finished: first
+            .finished
+            .iter()
+            .copied()
+            .chain(second.finished.iter().map(increase))
+            .collect(),
+    }
+}
+
+pub fn times(first: &ByteNFA, second: &ByteNFA) -> ByteNFA {
+    let increase = |&Node(n): &Node| Node(n + first.states);
+    let (second_delta, second_epsilon) = second.shifted(first.states);
+    let mut delta = first.delta.clone();
+    delta.extend(second_delta);
+    let mut epsilon = first.epsilon.clone();
+    epsilon.extend(second_epsilon);
+    for &node in first.finished.iter() {
+        epsilon
+            .entry(node)
+            .or_default()
+            .extend(second.starting.iter().map(increase));
+    }
+
+    ByteNFA {
+        states: first.states + second.states,
+        starting: first.starting.clone(),
+        delta,
+        epsilon,
+        finished: second.finished.iter().map(increase).collect(),
+    }
+}
+
+pub fn star(nfa: &ByteNFA) -> ByteNFA {
+    let increase = |&Node(n): &Node| Node(n + 1);
+    let fresh = Node(0);
+    let (delta, mut epsilon) = nfa.shifted(1);
+    epsilon.insert(fresh, nfa.starting.iter().map(increase).collect());
+    for node in nfa.finished.iter() {
+        epsilon.entry(increase(node)).or_default().insert(fresh);
+    }
+
+    ByteNFA {
+        states: nfa.states + 1,
+        starting: [fresh].into(),
+        delta,
+        epsilon,
+        finished: [fresh].into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::byte_nfa::*;
+
+    #[test]
+    pub fn test_two_byte_sequence() {
+        let nfa = times(&unit_byte(0x00), &unit_byte(0xFF));
+        assert!(nfa.is_match(&[0x00, 0xFF]));
+        assert!(!nfa.is_match(&[0xFF, 0x00]));
+        assert!(!nfa.is_match(&[0x00]));
+        assert!(!nfa.is_match(&[0x00, 0xFF, 0xFF]));
+    }
+
+    #[test]
+    pub fn test_plus_and_star() {
+        let nfa = times(
+            &star(&plus(&unit_byte(0x00), &unit_byte(0x7F))),
+            &unit_byte(0xFF),
+        );
+        assert!(nfa.is_match(&[0xFF]));
+        assert!(nfa.is_match(&[0x00, 0x7F, 0x00, 0xFF]));
+        assert!(!nfa.is_match(&[0x00, 0x01, 0xFF]));
+        assert!(!nfa.is_match(&[]));
+        assert!(star(&empty()).is_match(&[]));
+        assert!(!star(&empty()).is_match(&[0x00]));
+    }
+
+    #[test]
+    pub fn test_invalid_utf8_input() {
+        // 0xC3 0x28 is not valid UTF-8
+        let nfa = times(&unit_byte(0xC3), &unit_byte(0x28));
+        assert!(nfa.is_match(&[0xC3, 0x28]));
+        assert!(!nfa.is_match(&[0xC3]));
+    }
+}
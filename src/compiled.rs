@@ -0,0 +1,165 @@
+//! A compact, read-only form of `NFA` for large automata.
+//!
+//! Instead of hashing `(Node, ExtendedChar)` pairs, every node owns a slice of
+//! character-sorted edges in flat arrays, and each edge points at a sorted
+//! `u32` slice holding the epsilon closure of its targets.
+
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, NFA};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone)]
+pub struct CompiledNFA {
+    start: Vec<u32>,
+    accepts_empty: bool,
+    // edges of node `n` are `edges[offsets[n]..offsets[n + 1]]`
+    offsets: Vec<u32>,
+    edges: Vec<(char, Span)>,
+    wildcard: Vec<Span>,
+    // `NoneOf` and `Predicate` edges of each node
+    scanned: Vec<Vec<(ExtendedChar, Span)>>,
+    targets: Vec<u32>,
+    // already accepting once the input ends, following end anchors
+    accepting: Vec<bool>,
+}
+
+/// A range of `CompiledNFA::targets`.
+#[derive(Debug, Clone, Copy)]
+struct Span(u32, u32);
+
+impl NFA {
+    /// Converts the automaton into a `CompiledNFA`, taking every epsilon
+    /// closure once up front. Panics if the nodes or closures outgrow `u32`
+    /// indices.
+    pub fn compile(&self) -> CompiledNFA {
+        let small = |n: usize| u32::try_from(n).expect("automaton too large to compile");
+        let index = |Node(n): Node| small(n);
+        let closed = |targets: &mut Vec<u32>, set: &HashSet<Node>| {
+            let begin = small(targets.len());
+            let mut closure: Vec<u32> = self.epsilon_closure(set).into_iter().map(index).collect();
+            closure.sort();
+            targets.extend(closure);
+            Span(begin, small(targets.len()))
+        };
+
+        let mut outgoing = vec![Vec::new(); self.states];
+        for ((Node(n), ec), set) in self.delta.iter() {
+            outgoing[*n].push((ec, set));
+        }
+        let mut offsets = vec![0];
+        let mut edges = Vec::new();
+        let mut wildcard = vec![Span(0, 0); self.states];
+        let mut scanned = vec![Vec::new(); self.states];
+        let mut targets = Vec::new();
+        for (n, out) in outgoing.iter_mut().enumerate() {
+            out.sort_by_key(|&(ec, _)| ec);
+            for &(ec, set) in out.iter() {
+                match ec {
+                    ExtendedChar::Char(ch) => edges.push((*ch, closed(&mut targets, set))),
+                    ExtendedChar::Wildcard => wildcard[n] = closed(&mut targets, set),
+                    ExtendedChar::NoneOf(_) | ExtendedChar::Predicate(_) => {
+                        scanned[n].push((ec.clone(), closed(&mut targets, set)))
+                    }
+                    ExtendedChar::StartOfInput | ExtendedChar::EndOfInput => {}
+                }
+            }
+            offsets.push(small(edges.len()));
+        }
+
+        let mut start: Vec<u32> = self
+            .closure_at(&self.starting, true, false)
+            .into_iter()
+            .map(index)
+            .collect();
+        start.sort();
+        CompiledNFA {
+            start,
+            accepts_empty: self.accepts_empty(),
+            offsets,
+            edges,
+            wildcard,
+            scanned,
+            targets,
+            accepting: (0..self.states)
+                .map(|n| self.accepts_at(&[Node(n)].into(), false, true))
+                .collect(),
+        }
+    }
+}
+
+impl CompiledNFA {
+    /// Same result as `NFA::is_match` on the automaton this was compiled from.
+    pub fn is_match(&self, s: &str) -> bool {
+        if s.is_empty() {
+            return self.accepts_empty;
+        }
+        // `seen[n] == generation` marks `n` as already in `next`
+        let mut seen = vec![0usize; self.accepting.len()];
+        let mut active = self.start.clone();
+        let mut next = Vec::new();
+        for (generation, ch) in (1..).zip(s.chars()) {
+            next.clear();
+            for &node in active.iter() {
+                let n = node as usize;
+                let row = &self.edges[self.offsets[n] as usize..self.offsets[n + 1] as usize];
+                let exact = match row.binary_search_by_key(&ch, |&(c, _)| c) {
+                    Ok(i) => self.slice(row[i].1),
+                    Err(_) => &[],
+                };
+                let others = self.scanned[n]
+                    .iter()
+                    .filter(|(ec, _)| ec.matches(ch))
+                    .flat_map(|&(_, span)| self.slice(span));
+                let wildcard = self.slice(self.wildcard[n]);
+                for &target in exact.iter().chain(wildcard).chain(others) {
+                    if seen[target as usize] != generation {
+                        seen[target as usize] = generation;
+                        next.push(target);
+                    }
+                }
+            }
+            if next.is_empty() {
+                return false;
+            }
+            std::mem::swap(&mut active, &mut next);
+        }
+        active.iter().any(|&n| self.accepting[n as usize])
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.accepting.len()
+    }
+
+    fn slice(&self, Span(begin, end): Span) -> &[u32] {
+        &self.targets[begin as usize..end as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::*;
+    use crate::parser::parse;
+
+    #[test]
+    pub fn test_compile_agrees_with_nfa() {
+        let patterns = ["(ab|c)*d", "a.c", "^(a|b)*a$", "", "日(本|.)*"];
+        let inputs = [
+            "", "d", "abcd", "ac", "abc", "a日c", "aba", "bb", "日本", "日x本",
+        ];
+        for pattern in patterns {
+            let nfa = parse(pattern).unwrap();
+            let compiled = nfa.compile();
+            assert_eq!(compiled.num_states(), nfa.num_states());
+            for input in inputs {
+                assert_eq!(
+                    compiled.is_match(input),
+                    nfa.is_match(input),
+                    "{:?} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+        assert!(!union(&[]).compile().is_match(""));
+    }
+}
@@ -0,0 +1,822 @@
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, NFA};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// A deterministic automaton. Each state has at most one successor per
+/// character; `wildcard` holds the successor taken on any character without an
+/// explicit entry in `delta`. A missing transition rejects the input.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone)]
+pub struct DFA {
+    pub(crate) states: usize,
+    pub(crate) start: usize,
+    pub(crate) delta: HashMap<(usize, char), usize>,
+    pub(crate) wildcard: HashMap<usize, usize>,
+    pub(crate) finished: HashSet<usize>,
+    // the states `live` finds, computed by the first call that needs them
+    pub(crate) live: OnceLock<HashSet<usize>>,
+}
+
+impl DFA {
+    pub fn is_match(&self, s: &str) -> bool {
+        let mut state = self.start;
+        for ch in s.chars() {
+            match self.step(state, ch) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        self.finished.contains(&state)
+    }
+
+    /// Walks `s` until the input is rejected for good: the next character
+    /// has no transition or leads to a trap state, from which no finished
+    /// state can be reached. Returns how many characters were consumed
+    /// before that (all of them if the walk reaches the end) and whether the
+    /// state after the consumed prefix is finished.
+    pub fn run_prefix(&self, s: &str) -> (usize, bool) {
+        let live = self.live();
+        let mut state = self.start;
+        let mut consumed = 0;
+        for ch in s.chars() {
+            match self.step(state, ch) {
+                Some(next) if live.contains(&next) => state = next,
+                _ => break,
+            }
+            consumed += 1;
+        }
+        (consumed, self.finished.contains(&state))
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.states
+    }
+
+    pub fn start_state(&self) -> usize {
+        self.start
+    }
+
+    pub fn is_final(&self, state: usize) -> bool {
+        self.finished.contains(&state)
+    }
+
+    /// Every explicit transition as `(from, ch, to)`, sorted.
+    pub fn transitions(&self) -> Vec<(usize, char, usize)> {
+        let mut transitions: Vec<(usize, char, usize)> = self
+            .delta
+            .iter()
+            .map(|(&(from, ch), &to)| (from, ch, to))
+            .collect();
+        transitions.sort();
+        transitions
+    }
+
+    /// Every wildcard transition as `(from, to)`, sorted.
+    pub fn wildcard_transitions(&self) -> Vec<(usize, usize)> {
+        let mut transitions: Vec<(usize, usize)> = self
+            .wildcard
+            .iter()
+            .map(|(&from, &to)| (from, to))
+            .collect();
+        transitions.sort();
+        transitions
+    }
+
+    /// Merges equivalent states with Hopcroft's partition refinement and drops
+    /// states that are unreachable or can never reach a finished state.
+    /// Surviving states are renumbered in breadth-first order from the start.
+    pub fn minimize(&self) -> DFA {
+        let reachable = self.reachable();
+        let mut symbols: Vec<Option<char>> = self.delta.keys().map(|&(_, ch)| Some(ch)).collect();
+        symbols.sort();
+        symbols.dedup();
+        symbols.push(None);
+
+        // Complete the transition function over `symbols` with an explicit
+        // dead state so that every state has exactly one successor per symbol.
+        let dead = reachable.len();
+        let index: HashMap<usize, usize> =
+            reachable.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        let mut trans = vec![vec![dead; symbols.len()]; dead + 1];
+        for (i, &state) in reachable.iter().enumerate() {
+            for (k, &symbol) in symbols.iter().enumerate() {
+                let next = match symbol {
+                    Some(ch) => self.step(state, ch),
+                    None => self.wildcard.get(&state).copied(),
+                };
+                if let Some(next) = next {
+                    trans[i][k] = index[&next];
+                }
+            }
+        }
+        let mut inverse = vec![vec![Vec::new(); dead + 1]; symbols.len()];
+        for (source, row) in trans.iter().enumerate() {
+            for (k, &target) in row.iter().enumerate() {
+                inverse[k][target].push(source);
+            }
+        }
+
+        let (accepting, rejecting): (Vec<usize>, Vec<usize>) =
+            (0..=dead).partition(|&i| i != dead && self.finished.contains(&reachable[i]));
+        let mut blocks: Vec<Vec<usize>> = [accepting, rejecting]
+            .into_iter()
+            .filter(|block| !block.is_empty())
+            .collect();
+        let mut block_of = vec![0; dead + 1];
+        for (b, block) in blocks.iter().enumerate() {
+            for &state in block {
+                block_of[state] = b;
+            }
+        }
+        let mut work: HashSet<(usize, usize)> = (0..symbols.len()).map(|k| (0, k)).collect();
+        if blocks.len() == 2 {
+            let smaller = if blocks[0].len() <= blocks[1].len() {
+                0
+            } else {
+                1
+            };
+            work = (0..symbols.len()).map(|k| (smaller, k)).collect();
+        }
+
+        while let Some(&(splitter, k)) = work.iter().next() {
+            work.remove(&(splitter, k));
+            let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+            for &target in &blocks[splitter] {
+                for &source in &inverse[k][target] {
+                    predecessors
+                        .entry(block_of[source])
+                        .or_default()
+                        .push(source);
+                }
+            }
+            for (block, mut inside) in predecessors {
+                inside.sort();
+                inside.dedup();
+                if inside.len() == blocks[block].len() {
+                    continue;
+                }
+                let outside: Vec<usize> = blocks[block]
+                    .iter()
+                    .copied()
+                    .filter(|state| inside.binary_search(state).is_err())
+                    .collect();
+                let new_block = blocks.len();
+                for &state in &inside {
+                    block_of[state] = new_block;
+                }
+                blocks[block] = outside;
+                blocks.push(inside);
+                for symbol in 0..symbols.len() {
+                    // If the old block was still waiting both halves must be
+                    // processed; otherwise the smaller half suffices.
+                    if work.contains(&(block, symbol))
+                        || blocks[new_block].len() <= blocks[block].len()
+                    {
+                        work.insert((new_block, symbol));
+                    } else {
+                        work.insert((block, symbol));
+                    }
+                }
+            }
+        }
+
+        // A block is dead if it cannot reach an accepting block.
+        let mut live: HashSet<usize> = (0..dead)
+            .filter(|&i| self.finished.contains(&reachable[i]))
+            .map(|i| block_of[i])
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in &blocks {
+                if let Some(&rep) = block.first() {
+                    let b = block_of[rep];
+                    if !live.contains(&b) && trans[rep].iter().any(|t| live.contains(&block_of[*t]))
+                    {
+                        live.insert(b);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        // Renumber live blocks breadth-first from the start block. A dead
+        // block is only materialized when an explicit character must reject
+        // while the wildcard would not.
+        let wildcard_index = symbols.len() - 1;
+        let mut numbering: HashMap<usize, usize> = HashMap::new();
+        let mut order: Vec<usize> = Vec::new();
+        let start_block = block_of[0];
+        numbering.insert(start_block, 0);
+        order.push(start_block);
+        let mut i = 0;
+        let mut needs_dead = false;
+        while i < order.len() {
+            let rep = blocks[order[i]][0];
+            for (k, &target) in trans[rep].iter().enumerate() {
+                let target_block = block_of[target];
+                if !live.contains(&target_block) {
+                    let wildcard_live = live.contains(&block_of[trans[rep][wildcard_index]]);
+                    needs_dead |= k != wildcard_index && wildcard_live;
+                    continue;
+                }
+                if let Entry::Vacant(entry) = numbering.entry(target_block) {
+                    entry.insert(order.len());
+                    order.push(target_block);
+                }
+            }
+            i += 1;
+        }
+        let dead_state = order.len();
+
+        let mut delta = HashMap::new();
+        let mut wildcard = HashMap::new();
+        let mut finished = HashSet::new();
+        for (id, &block) in order.iter().enumerate() {
+            let rep = blocks[block][0];
+            if rep != dead && self.finished.contains(&reachable[rep]) {
+                finished.insert(id);
+            }
+            let target_of = |k: usize| numbering.get(&block_of[trans[rep][k]]).copied();
+            let default = target_of(wildcard_index);
+            if let Some(default) = default {
+                wildcard.insert(id, default);
+            }
+            for (k, &symbol) in symbols[..wildcard_index].iter().enumerate() {
+                let ch = symbol.unwrap();
+                match (target_of(k), default) {
+                    (Some(target), Some(default)) if target == default => {}
+                    (Some(target), _) => {
+                        delta.insert((id, ch), target);
+                    }
+                    (None, Some(_)) => {
+                        delta.insert((id, ch), dead_state);
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+
+        DFA {
+            states: order.len() + usize::from(needs_dead),
+            start: 0,
+            delta,
+            wildcard,
+            finished,
+            live: OnceLock::new(),
+        }
+    }
+
+    fn reachable(&self) -> Vec<usize> {
+        let mut seen = vec![self.start];
+        let mut visited: HashSet<usize> = [self.start].into();
+        let mut i = 0;
+        while i < seen.len() {
+            let state = seen[i];
+            let targets = self
+                .delta
+                .iter()
+                .filter(|((source, _), _)| *source == state)
+                .map(|(_, &target)| target)
+                .chain(self.wildcard.get(&state).copied());
+            for target in targets.collect::<Vec<_>>() {
+                if visited.insert(target) {
+                    seen.push(target);
+                }
+            }
+            i += 1;
+        }
+        seen
+    }
+
+    /// The states from which some finished state can be reached.
+    fn live(&self) -> &HashSet<usize> {
+        self.live.get_or_init(|| self.find_live())
+    }
+
+    fn find_live(&self) -> HashSet<usize> {
+        let mut live = self.finished.clone();
+        let edges: Vec<(usize, usize)> = self
+            .delta
+            .iter()
+            .map(|(&(source, _), &target)| (source, target))
+            .chain(
+                self.wildcard
+                    .iter()
+                    .map(|(&source, &target)| (source, target)),
+            )
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(source, target) in edges.iter() {
+                if live.contains(&target) && live.insert(source) {
+                    changed = true;
+                }
+            }
+        }
+        live
+    }
+
+    pub(crate) fn step(&self, state: usize, ch: char) -> Option<usize> {
+        self.delta
+            .get(&(state, ch))
+            .or_else(|| self.wildcard.get(&state))
+            .copied()
+    }
+
+    /// Flattens the transition function into a `DfaTable` for matching by
+    /// array indexing instead of hashing every character.
+    pub fn to_table(&self) -> DfaTable {
+        let mut symbols: Vec<char> = self.delta.keys().map(|&(_, ch)| ch).collect();
+        symbols.sort();
+        symbols.dedup();
+        let columns = symbols.len() + 1;
+
+        let mut ascii = [symbols.len(); 128];
+        let mut others = Vec::new();
+        for (column, &ch) in symbols.iter().enumerate() {
+            if ch.is_ascii() {
+                ascii[ch as usize] = column;
+            } else {
+                others.push((ch, column));
+            }
+        }
+
+        let mut table = Vec::with_capacity(self.states * columns);
+        for state in 0..self.states {
+            for &ch in symbols.iter() {
+                table.push(self.step(state, ch).unwrap_or(DEAD));
+            }
+            table.push(self.wildcard.get(&state).copied().unwrap_or(DEAD));
+        }
+
+        DfaTable {
+            columns,
+            ascii,
+            others,
+            table,
+            start: self.start,
+            finished: (0..self.states)
+                .map(|state| self.finished.contains(&state))
+                .collect(),
+        }
+    }
+}
+
+const DEAD: usize = usize::MAX;
+
+/// A `DFA` compiled to a dense row-per-state transition table. Characters are
+/// mapped to compact column indices, ASCII through a direct lookup and the rest
+/// through a sorted list; the last column stands for every character outside
+/// the alphabet.
+#[derive(Debug, Clone)]
+pub struct DfaTable {
+    columns: usize,
+    ascii: [usize; 128],
+    others: Vec<(char, usize)>,
+    table: Vec<usize>,
+    start: usize,
+    finished: Vec<bool>,
+}
+
+impl DfaTable {
+    pub fn is_match(&self, s: &str) -> bool {
+        let mut state = self.start;
+        for ch in s.chars() {
+            state = self.table[state * self.columns + self.column(ch)];
+            if state == DEAD {
+                return false;
+            }
+        }
+        self.finished[state]
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.finished.len()
+    }
+
+    fn column(&self, ch: char) -> usize {
+        if ch.is_ascii() {
+            return self.ascii[ch as usize];
+        }
+        match self.others.binary_search_by_key(&ch, |&(other, _)| other) {
+            Ok(i) => self.others[i].1,
+            Err(_) => self.columns - 1,
+        }
+    }
+}
+
+impl NFA {
+    /// Determinizes the automaton with the subset construction. Every reachable
+    /// non-empty set of nodes becomes one DFA state, numbered in discovery order
+    /// starting from the set of starting nodes. Anchors are resolved during the
+    /// construction: start anchors are only followed from the starting nodes,
+    /// and a state is finished if end anchors lead it to a finished node.
+    ///
+    /// Panics on `Predicate` edges: a DFA only tells apart explicit
+    /// characters and the rest, and a predicate can split the rest.
+    pub fn to_dfa(&self) -> DFA {
+        assert!(
+            !self.has_predicates(),
+            "predicate edges can't be determinized"
+        );
+        let alphabet = self.alphabet();
+
+        let mut ids: HashMap<Vec<Node>, usize> = HashMap::new();
+        let mut pending: Vec<Vec<Node>> = Vec::new();
+        let mut delta = HashMap::new();
+        let mut wildcard = HashMap::new();
+        let mut finished = HashSet::new();
+
+        let start = sorted(self.closure_at(&self.starting, true, false));
+        ids.insert(start.clone(), 0);
+        pending.push(start);
+
+        while let Some(set) = pending.pop() {
+            let id = ids[&set];
+            if self.accepts_at(&set.iter().copied().collect(), false, true) {
+                finished.insert(id);
+            }
+            let mut targets: Vec<(Option<char>, Vec<Node>)> = alphabet
+                .iter()
+                .map(|&ch| (Some(ch), self.successors(&set, Some(ch))))
+                .collect();
+            let other = self.successors(&set, None);
+            // a character a `NoneOf` edge excludes needs an explicit move to
+            // the empty set, or `step` would fall back to the wildcard
+            let fallback = !other.is_empty();
+            targets.push((None, other));
+            for (ch, target) in targets {
+                if target.is_empty() && !(fallback && ch.is_some()) {
+                    continue;
+                }
+                let next = ids.len();
+                let target_id = *ids.entry(target.clone()).or_insert_with(|| {
+                    pending.push(target);
+                    next
+                });
+                match ch {
+                    Some(ch) => delta.insert((id, ch), target_id),
+                    None => wildcard.insert(id, target_id),
+                };
+            }
+        }
+
+        DFA {
+            states: ids.len(),
+            start: 0,
+            delta,
+            wildcard,
+            finished,
+            live: OnceLock::new(),
+        }
+    }
+
+    /// A canonical automaton for the language: the minimized DFA, renumbered
+    /// breadth-first from the start by following explicit characters in order
+    /// and then the wildcard, turned back into an NFA. Two automata recognize
+    /// the same language iff their canonical forms are structurally equal.
+    pub fn canonicalize(&self) -> NFA {
+        let dfa = self.to_dfa().minimize();
+        let mut outgoing: HashMap<usize, Vec<(char, usize)>> = HashMap::new();
+        for (&(state, ch), &target) in dfa.delta.iter() {
+            outgoing.entry(state).or_default().push((ch, target));
+        }
+        for edges in outgoing.values_mut() {
+            edges.sort();
+        }
+
+        let mut order = vec![dfa.start];
+        let mut numbering: HashMap<usize, usize> = [(dfa.start, 0)].into();
+        let mut i = 0;
+        while i < order.len() {
+            let state = order[i];
+            let explicit = outgoing.get(&state).into_iter().flatten().map(|&(_, t)| t);
+            for target in explicit.chain(dfa.wildcard.get(&state).copied()) {
+                if let Entry::Vacant(entry) = numbering.entry(target) {
+                    entry.insert(order.len());
+                    order.push(target);
+                }
+            }
+            i += 1;
+        }
+
+        // The NFA follows a wildcard edge alongside an explicit one, so a state
+        // with both gets a `NoneOf` edge excluding its explicit characters.
+        let node = |state: &usize| Node(numbering[state]);
+        let mut delta = HashMap::new();
+        for (state, edges) in outgoing.iter() {
+            for (ch, target) in edges {
+                delta.insert(
+                    (node(state), ExtendedChar::Char(*ch)),
+                    [node(target)].into(),
+                );
+            }
+        }
+        for (state, target) in dfa.wildcard.iter() {
+            let label = match outgoing.get(state) {
+                Some(edges) => ExtendedChar::NoneOf(edges.iter().map(|&(ch, _)| ch).collect()),
+                None => ExtendedChar::Wildcard,
+            };
+            delta.insert((node(state), label), [node(target)].into());
+        }
+
+        NFA {
+            states: order.len(),
+            starting: [Node(0)].into(),
+            delta,
+            epsilon: [].into(),
+            finished: dfa.finished.iter().map(node).collect(),
+            labels: [].into(),
+            branches: [].into(),
+        }
+    }
+
+    /// Nodes reachable from `set` on `ch`, or on a character outside the
+    /// explicit alphabet when `ch` is `None`.
+    fn successors(&self, set: &[Node], ch: Option<char>) -> Vec<Node> {
+        let mut result = HashSet::new();
+        for &node in set {
+            if let Some(ch) = ch {
+                if let Some(next) = self.delta.get(&(node, ExtendedChar::Char(ch))) {
+                    result.extend(next.iter().copied());
+                }
+            }
+            if let Some(next) = self.delta.get(&(node, ExtendedChar::Wildcard)) {
+                result.extend(next.iter().copied());
+            }
+        }
+        // `None` stands for characters outside the alphabet, which includes
+        // everything a `NoneOf` edge excludes
+        for ((node, ec), next) in self.delta.iter() {
+            if let ExtendedChar::NoneOf(_) = ec {
+                if ch.is_none_or(|ch| ec.matches(ch)) && set.contains(node) {
+                    result.extend(next.iter().copied());
+                }
+            }
+        }
+        sorted(self.epsilon_closure(&result))
+    }
+}
+
+fn sorted(nodes: impl IntoIterator<Item = Node>) -> Vec<Node> {
+    let mut nodes: Vec<Node> = nodes.into_iter().collect();
+    nodes.sort();
+    nodes
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dfa::DFA;
+    use crate::nfa::builder::NFABuilder;
+    use crate::nfa::*;
+    use std::sync::OnceLock;
+
+    fn ab_star_c() -> NFA {
+        times(
+            &star(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &unit(ExtendedChar::Char('c')),
+        )
+    }
+
+    #[test]
+    pub fn test_to_dfa_agrees_with_nfa() {
+        let nfa = ab_star_c();
+        let dfa = nfa.to_dfa();
+        let inputs = [
+            "", "c", "abc", "ababc", "abababc", "ab", "abab", "ac", "abcc", "bac", "cab", "aabbc",
+            "abcabc", "x", "abxc",
+        ];
+        for input in inputs {
+            assert_eq!(dfa.is_match(input), nfa.is_match(input), "on {:?}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_run_prefix() {
+        let dfa = literal("abc").to_dfa();
+        assert_eq!(dfa.run_prefix("abx"), (2, false));
+        assert_eq!(dfa.run_prefix("abc"), (3, true));
+        assert_eq!(dfa.run_prefix("abcabc"), (3, true));
+        assert_eq!(dfa.run_prefix("ab"), (2, false));
+        assert_eq!(dfa.run_prefix(""), (0, false));
+        let dfa = ab_star_c().to_dfa();
+        assert_eq!(dfa.run_prefix("ababcx"), (5, true));
+        assert_eq!(dfa.run_prefix("abac"), (3, false));
+        // the wildcard is followed, then 'b' has nowhere to go
+        let dfa = plus(&literal("ab"), &times(&dot(), &literal("c"))).to_dfa();
+        assert_eq!(dfa.run_prefix("zb"), (1, false));
+        assert_eq!(dfa.run_prefix("zc"), (2, true));
+        assert_eq!(union(&[]).to_dfa().run_prefix("a"), (0, false));
+        // the live states are found once, by the first call
+        assert!(dfa.live.get().is_some());
+        let minimized = dfa.minimize();
+        assert!(minimized.live.get().is_none());
+        assert_eq!(minimized.run_prefix("zb"), (1, false));
+    }
+
+    #[test]
+    pub fn test_to_dfa_wildcard() {
+        let nfa = times(
+            &unit(ExtendedChar::Wildcard),
+            &unit(ExtendedChar::Char('a')),
+        );
+        let dfa = nfa.to_dfa();
+        assert!(dfa.is_match("aa"));
+        assert!(dfa.is_match("za"));
+        assert!(!dfa.is_match("az"));
+        assert!(!dfa.is_match("a"));
+    }
+
+    fn strings_over(alphabet: &[char], max_len: usize) -> Vec<String> {
+        let mut result = vec![String::new()];
+        let mut frontier = vec![String::new()];
+        for _ in 0..max_len {
+            frontier = frontier
+                .iter()
+                .flat_map(|prefix| alphabet.iter().map(move |&ch| format!("{}{}", prefix, ch)))
+                .collect();
+            result.extend(frontier.iter().cloned());
+        }
+        result
+    }
+
+    #[test]
+    pub fn test_minimize_merges_states() {
+        let nfa = plus(
+            &times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('c')),
+            ),
+            &times(
+                &unit(ExtendedChar::Char('b')),
+                &unit(ExtendedChar::Char('c')),
+            ),
+        );
+        let dfa = nfa.to_dfa();
+        assert_eq!(dfa.num_states(), 5);
+        let minimized = dfa.minimize();
+        assert_eq!(minimized.num_states(), 3);
+        for input in strings_over(&['a', 'b', 'c', 'd'], 4) {
+            assert_eq!(
+                minimized.is_match(&input),
+                dfa.is_match(&input),
+                "on {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_minimize_drops_unreachable_and_dead() {
+        // 0 -a-> 1 -a-> 2 (final), 0 -b-> 3 (dead end), 4 unreachable and final
+        let dfa = DFA {
+            states: 5,
+            start: 0,
+            delta: [((0, 'a'), 1), ((1, 'a'), 2), ((0, 'b'), 3), ((4, 'a'), 2)].into(),
+            wildcard: [].into(),
+            finished: [2, 4].into(),
+            live: OnceLock::new(),
+        };
+        let minimized = dfa.minimize();
+        assert_eq!(minimized.num_states(), 3);
+        for input in strings_over(&['a', 'b'], 4) {
+            assert_eq!(
+                minimized.is_match(&input),
+                dfa.is_match(&input),
+                "on {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_minimize_star_and_wildcard() {
+        let nfa = times(
+            &star(&plus(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Wildcard),
+            )),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let dfa = nfa.to_dfa();
+        let minimized = dfa.minimize();
+        assert_eq!(minimized.num_states(), 2);
+        for input in strings_over(&['a', 'b', 'z'], 5) {
+            assert_eq!(
+                minimized.is_match(&input),
+                dfa.is_match(&input),
+                "on {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_minimize_keeps_rejecting_char_under_wildcard() {
+        // 0 accepts any character except 'a'
+        let dfa = DFA {
+            states: 3,
+            start: 0,
+            delta: [((0, 'a'), 2)].into(),
+            wildcard: [(0, 1)].into(),
+            finished: [1].into(),
+            live: OnceLock::new(),
+        };
+        let minimized = dfa.minimize();
+        assert_eq!(minimized.num_states(), 3);
+        assert!(minimized.is_match("b"));
+        assert!(!minimized.is_match("a"));
+        assert!(!minimized.is_match(""));
+    }
+
+    #[test]
+    pub fn test_minimize_empty_language() {
+        let dfa = DFA {
+            states: 2,
+            start: 0,
+            delta: [((0, 'a'), 1)].into(),
+            wildcard: [].into(),
+            finished: [].into(),
+            live: OnceLock::new(),
+        };
+        let minimized = dfa.minimize();
+        assert_eq!(minimized.num_states(), 1);
+        assert!(!minimized.is_match(""));
+        assert!(!minimized.is_match("a"));
+    }
+
+    #[test]
+    pub fn test_table_agrees_with_dfa() {
+        let dfa = ab_star_c().to_dfa();
+        let table = dfa.to_table();
+        assert_eq!(table.num_states(), dfa.num_states());
+        for s in ["", "c", "abc", "ababc", "ab", "abcc", "ac", "xc", "日"] {
+            assert_eq!(table.is_match(s), dfa.is_match(s), "{:?}", s);
+        }
+    }
+
+    #[test]
+    pub fn test_table_wildcard_and_non_ascii() {
+        let nfa = times(
+            &times(
+                &unit(ExtendedChar::Char('é')),
+                &unit(ExtendedChar::Wildcard),
+            ),
+            &unit(ExtendedChar::Char('a')),
+        );
+        let table = nfa.to_dfa().to_table();
+        assert!(table.is_match("éxa"));
+        assert!(table.is_match("é日a"));
+        assert!(table.is_match("ééa"));
+        assert!(!table.is_match("exa"));
+        assert!(!table.is_match("éx"));
+    }
+
+    #[test]
+    pub fn test_to_dfa_anchors() {
+        let a = unit(ExtendedChar::Char('a'));
+        let nfa = times(&star(&plus(&start_anchor(), &a)), &times(&a, &end_anchor()));
+        let dfa = nfa.to_dfa();
+        for s in ["", "a", "aa", "aaa"] {
+            assert_eq!(dfa.is_match(s), nfa.is_match(s), "{:?}", s);
+        }
+        assert!(!times(&a, &start_anchor()).to_dfa().is_match("a"));
+    }
+
+    #[test]
+    pub fn test_canonicalize() {
+        let mut builder = NFABuilder::new();
+        let only = builder.add_state();
+        builder
+            .mark_start(only)
+            .mark_final(only)
+            .add_transition(only, 'a', only);
+        let by_hand = builder.build().unwrap();
+        let a = unit(ExtendedChar::Char('a'));
+        assert_eq!(star(&a).canonicalize(), by_hand.canonicalize());
+        assert_eq!(star_fresh(&a).canonicalize(), by_hand.canonicalize());
+        assert_ne!(a.canonicalize(), by_hand.canonicalize());
+
+        // a redundant explicit character must not change the numbering
+        let redundant = plus(
+            &times(&dot(), &unit(ExtendedChar::Char('b'))),
+            &times(&a, &dot()),
+        );
+        let wildcard = plus(
+            &times(&dot(), &unit(ExtendedChar::Char('b'))),
+            &times(&a, &plus(&dot(), &unit(ExtendedChar::Char('b')))),
+        );
+        let canonical = redundant.canonicalize();
+        assert_eq!(canonical, wildcard.canonicalize());
+        for s in ["ab", "xb", "ax", "a日", "bx", "a", "abb"] {
+            assert_eq!(canonical.is_match(s), redundant.is_match(s), "{:?}", s);
+        }
+    }
+}
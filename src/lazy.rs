@@ -0,0 +1,165 @@
+//! A pattern matched through a DFA built lazily, one transition at a time.
+//!
+//! Unlike `Regex`, which determinizes the whole automaton up front, only the
+//! sets of nodes that inputs actually reach are ever constructed. They are
+//! cached across calls, so inputs sharing structure quickly run on cached
+//! transitions alone.
+
+use crate::nfa::node::Node;
+use crate::nfa::{ScannedEdges, NFA};
+use crate::parser::{parse, ParseError};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+
+/// A pattern with a cache of subset-construction states shared by every
+/// call to `is_match`. The cache is behind a `Mutex`, so a `LazyRegex` can be
+/// shared between threads, though their matches then take turns. It is never
+/// evicted and grows with the number of distinct sets reached.
+///
+/// ```
+/// use reg::lazy::LazyRegex;
+///
+/// # fn main() -> Result<(), reg::parser::ParseError> {
+/// let re = LazyRegex::new("(ab)*c")?;
+/// assert!(re.is_match("ababc"));
+/// assert!(!re.is_match("abab"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LazyRegex {
+    nfa: NFA,
+    cache: Mutex<Cache>,
+}
+
+/// Subset-construction states discovered so far. State 0 is the start,
+/// kept apart from the rest because only it follows start anchors.
+#[derive(Debug)]
+struct Cache {
+    sets: Vec<HashSet<Node>>,
+    ids: HashMap<Vec<Node>, usize>,
+    // whether each state accepts once the input ends there
+    accepting: Vec<bool>,
+    next: HashMap<(usize, char), usize>,
+    scanned: ScannedEdges,
+}
+
+impl Cache {
+    fn new(nfa: &NFA) -> Cache {
+        let start = nfa.closure_at(&nfa.starting, true, false);
+        Cache {
+            accepting: vec![nfa.accepts_at(&start, true, true)],
+            sets: vec![start],
+            ids: HashMap::new(),
+            next: HashMap::new(),
+            scanned: nfa.scanned_edges(),
+        }
+    }
+
+    fn step(&mut self, nfa: &NFA, state: usize, ch: char) -> usize {
+        if let Some(&next) = self.next.get(&(state, ch)) {
+            return next;
+        }
+        let set = nfa.step_set(&self.sets[state], ch, &self.scanned);
+        let mut key: Vec<Node> = set.iter().copied().collect();
+        key.sort();
+        let next = match self.ids.get(&key) {
+            Some(&id) => id,
+            None => {
+                let id = self.sets.len();
+                self.accepting.push(nfa.accepts_at(&set, false, true));
+                self.sets.push(set);
+                self.ids.insert(key, id);
+                id
+            }
+        };
+        self.next.insert((state, ch), next);
+        next
+    }
+}
+
+impl LazyRegex {
+    /// Parses `pattern` with the syntax described in `parser::parse`.
+    pub fn new(pattern: &str) -> Result<LazyRegex, ParseError> {
+        Ok(LazyRegex::from(parse(pattern)?))
+    }
+
+    /// Whether the whole of `s` matches, following cached transitions where
+    /// they exist and adding the rest to the cache.
+    pub fn is_match(&self, s: &str) -> bool {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut state = 0;
+        for ch in s.chars() {
+            state = cache.step(&self.nfa, state, ch);
+            if cache.sets[state].is_empty() {
+                return false;
+            }
+        }
+        cache.accepting[state]
+    }
+
+    /// The number of subset-construction states built so far.
+    pub fn num_cached_states(&self) -> usize {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sets
+            .len()
+    }
+
+    pub fn as_nfa(&self) -> &NFA {
+        &self.nfa
+    }
+}
+
+impl From<NFA> for LazyRegex {
+    fn from(nfa: NFA) -> LazyRegex {
+        LazyRegex {
+            cache: Mutex::new(Cache::new(&nfa)),
+            nfa,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lazy::*;
+    use crate::nfa::{digit, times};
+
+    #[test]
+    pub fn test_lazy_agrees_with_nfa() {
+        let patterns = ["(ab|c)*d", "a.c", "^(a|b)*a$", "", "日(本|.)*", "a*"];
+        let inputs = [
+            "", "d", "abcd", "ac", "abc", "a日c", "aba", "bb", "日本", "日x本", "aaa",
+        ];
+        for pattern in patterns {
+            let re = LazyRegex::new(pattern).unwrap();
+            // twice, so the second pass runs on cached transitions
+            for _ in 0..2 {
+                for input in inputs {
+                    assert_eq!(
+                        re.is_match(input),
+                        re.as_nfa().is_match(input),
+                        "{:?} on {:?}",
+                        pattern,
+                        input
+                    );
+                }
+            }
+        }
+        let re = LazyRegex::from(times(&digit(), &digit()));
+        assert!(re.is_match("4٣"));
+        assert!(!re.is_match("4x"));
+    }
+
+    #[test]
+    pub fn test_lazy_reuses_cache() {
+        let re = LazyRegex::new("(ab|c)*d").unwrap();
+        assert_eq!(re.num_cached_states(), 1);
+        assert!(re.is_match("ababcd"));
+        let built = re.num_cached_states();
+        assert!(re.is_match("cabd"));
+        assert!(re.is_match("abcabd"));
+        assert_eq!(re.num_cached_states(), built);
+    }
+}
@@ -0,0 +1,11 @@
+pub mod aho_corasick;
+pub mod byte_nfa;
+pub mod compiled;
+pub mod dfa;
+pub mod lazy;
+mod macros;
+pub mod nfa;
+pub mod parser;
+pub mod regex;
+
+pub use crate::regex::Regex;
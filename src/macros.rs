@@ -0,0 +1,101 @@
+/// Builds an `NFA` from the combinators in `nfa`, checked by the compiler
+/// instead of `parser::parse` at run time.
+///
+/// Juxtaposed atoms are concatenated with `times`, `|` folds `plus` from the
+/// left and a postfix `*` applies `star_fresh`, binding tighter than both.
+/// An atom is an identifier, which matches its own name, a character or
+/// string literal, `.` for `dot` or a parenthesized group. An empty
+/// invocation (or branch) gives `empty`.
+///
+/// ```
+/// use reg::nfa;
+///
+/// let nfa = nfa![(ab | 'c')* . "d"];
+/// assert!(nfa.is_match("abcxd"));
+/// assert!(!nfa.is_match("abd"));
+/// ```
+#[macro_export]
+macro_rules! nfa {
+    // alternation: munch one branch at a time into `[$cur]`, folding
+    // finished branches into `[$acc]`
+    (@alt [] [$($cur:tt)*] | $($rest:tt)*) => {
+        $crate::nfa!(@alt [$crate::nfa!(@cat [] $($cur)*)] [] $($rest)*)
+    };
+    (@alt [$acc:expr] [$($cur:tt)*] | $($rest:tt)*) => {
+        $crate::nfa!(@alt [$crate::nfa::plus(&$acc, &$crate::nfa!(@cat [] $($cur)*))] [] $($rest)*)
+    };
+    (@alt [$($acc:tt)*] [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::nfa!(@alt [$($acc)*] [$($cur)* $next] $($rest)*)
+    };
+    (@alt [] [$($cur:tt)*]) => {
+        $crate::nfa!(@cat [] $($cur)*)
+    };
+    (@alt [$acc:expr] [$($cur:tt)*]) => {
+        $crate::nfa::plus(&$acc, &$crate::nfa!(@cat [] $($cur)*))
+    };
+
+    // concatenation, with `[$acc]` holding the atoms so far
+    (@cat [] $atom:tt * $($rest:tt)*) => {
+        $crate::nfa!(@cat [$crate::nfa::star_fresh(&$crate::nfa!(@atom $atom))] $($rest)*)
+    };
+    (@cat [] $atom:tt $($rest:tt)*) => {
+        $crate::nfa!(@cat [$crate::nfa!(@atom $atom)] $($rest)*)
+    };
+    (@cat []) => {
+        $crate::nfa::empty()
+    };
+    (@cat [$acc:expr] $atom:tt * $($rest:tt)*) => {
+        $crate::nfa!(@cat [$crate::nfa::times(&$acc, &$crate::nfa::star_fresh(&$crate::nfa!(@atom $atom)))] $($rest)*)
+    };
+    (@cat [$acc:expr] $atom:tt $($rest:tt)*) => {
+        $crate::nfa!(@cat [$crate::nfa::times(&$acc, &$crate::nfa!(@atom $atom))] $($rest)*)
+    };
+    (@cat [$acc:expr]) => {
+        $acc
+    };
+
+    (@atom .) => {
+        $crate::nfa::dot()
+    };
+    (@atom ($($inner:tt)*)) => {
+        $crate::nfa!($($inner)*)
+    };
+    (@atom $name:ident) => {
+        $crate::nfa::NFA::from(stringify!($name))
+    };
+    (@atom $literal:literal) => {
+        $crate::nfa::NFA::from($literal)
+    };
+
+    ($($tokens:tt)*) => {
+        $crate::nfa!(@alt [] [] $($tokens)*)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::*;
+
+    #[test]
+    pub fn test_nfa_macro_matches_manual_composition() {
+        let (a, b, c) = (NFA::from("a"), NFA::from("b"), NFA::from("c"));
+        assert_eq!(nfa![a b | c], plus(&times(&a, &b), &c));
+        assert_eq!(nfa![a | b | c], plus(&plus(&a, &b), &c));
+        assert_eq!(nfa![(a | b) * c], times(&star_fresh(&plus(&a, &b)), &c));
+        assert_eq!(nfa![a b*], times(&a, &star_fresh(&b)));
+        let a_dot = times(&unit(ExtendedChar::Char('a')), &dot());
+        assert_eq!(nfa!['a' . "bc"], times(&a_dot, &literal("bc")));
+        assert_eq!(nfa![abc], literal("abc"));
+        assert_eq!(nfa![], empty());
+        assert_eq!(nfa![a |], plus(&a, &empty()));
+    }
+
+    #[test]
+    pub fn test_nfa_macro_agrees_with_parser() {
+        let nfa = nfa![(ab | c) * d];
+        let parsed = crate::parser::parse("(ab|c)*d").unwrap();
+        for input in ["d", "abd", "cabccd", "abc", "ad", ""] {
+            assert_eq!(nfa.is_match(input), parsed.is_match(input), "{:?}", input);
+        }
+    }
+}
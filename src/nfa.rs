@@ -1,287 +1,4414 @@
+pub mod builder;
+mod elimination;
 pub mod node;
+#[cfg(feature = "serde")]
+mod serialize;
+// threads are unavailable on wasm32, which only gets the sequential matchers
+#[cfg(not(target_arch = "wasm32"))]
+mod parallel;
+mod stepper;
 use char_stream::CharStream;
 use node::Node;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::ops;
+use std::sync::{Arc, OnceLock};
+use stepper::Stepper;
 
-#[derive(Debug)]
+/// A nondeterministic automaton with epsilon transitions.
+///
+/// `PartialEq` is structural: two automata compare equal only if they have the
+/// same node numbering, starting and finished sets, transitions, labels and
+/// alternatives recorded by `plus`. Use `equivalent` to compare the languages
+/// they recognize.
+///
+/// `Debug` and `Display` both print the states, the start and final sets and
+/// one sorted line `p --c--> {q, ...}` per transition. Every set is sorted as
+/// well, so equal automata always print identically, whatever order their
+/// hash maps iterate in.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "serialize::Unchecked")
+)]
 pub struct NFA {
-    states: usize,
-    starting: HashSet<Node>,
-    delta: HashMap<(Node, ExtendedChar), HashSet<Node>>,
-    finished: HashSet<Node>,
+    pub(crate) states: usize,
+    #[cfg_attr(feature = "serde", serde(with = "serialize::nodes"))]
+    pub(crate) starting: HashSet<Node>,
+    #[cfg_attr(feature = "serde", serde(with = "serialize::delta"))]
+    pub(crate) delta: HashMap<(Node, ExtendedChar), HashSet<Node>>,
+    /// Transitions taken without consuming input.
+    #[cfg_attr(feature = "serde", serde(with = "serialize::epsilon"))]
+    pub(crate) epsilon: HashMap<Node, HashSet<Node>>,
+    #[cfg_attr(feature = "serde", serde(with = "serialize::nodes"))]
+    pub(crate) finished: HashSet<Node>,
+    /// Token ids attached to finished nodes by `labeled`, for `which_match`.
+    /// Combinators that keep the nodes of their operands (`plus`, `times`,
+    /// `union`, `concat`, `star_fresh`, ...) keep their labels as well; the
+    /// others start over without any.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "HashMap::is_empty",
+            with = "serialize::node_map"
+        )
+    )]
+    pub(crate) labels: HashMap<Node, u32>,
+    /// The alternative of a `plus` or `union` that each starting node belongs
+    /// to, for `match_branch`. Empty when the automaton isn't an alternation,
+    /// or was built by a combinator that doesn't keep track, in which case
+    /// every starting node belongs to alternative 0.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            default,
+            skip_serializing_if = "HashMap::is_empty",
+            with = "serialize::node_map"
+        )
+    )]
+    pub(crate) branches: HashMap<Node, usize>,
 }
 
-#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
+/// Label of a transition in `delta`.
+///
+/// When stepping on a character `c`, a node follows both its `Char(c)` edge and
+/// its `Wildcard` edge, if present. The wildcard is not a fallback for a
+/// missing exact edge: both successor sets are taken, which is what makes
+/// `.` and a literal at the same position behave as alternatives.
+///
+/// The two anchor labels consume no input. They are followed like epsilon
+/// edges, but only at the position they assert.
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExtendedChar {
     Char(char),
-    Wildcard
+    /// Matches any single character.
+    Wildcard,
+    /// Holds only before the first character of the input.
+    StartOfInput,
+    /// Holds only after the last character of the input.
+    EndOfInput,
+    /// Matches any single character not in the list, which is kept sorted
+    /// and free of duplicates. Unlike `Char` and `Wildcard` these edges can't
+    /// be looked up by character, so a node's `NoneOf` edges are scanned.
+    NoneOf(Vec<char>),
+    /// Matches any single character the predicate holds for. Scanned like
+    /// `NoneOf`; `to_dfa` can't represent these edges, and serializing
+    /// them fails.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Predicate(Predicate),
+}
+
+impl ExtendedChar {
+    /// Whether an edge with this label consumes `ch`. Anchors consume nothing.
+    pub fn matches(&self, ch: char) -> bool {
+        match self {
+            ExtendedChar::Char(c) => *c == ch,
+            ExtendedChar::Wildcard => true,
+            ExtendedChar::NoneOf(excluded) => excluded.binary_search(&ch).is_err(),
+            ExtendedChar::Predicate(predicate) => predicate.test(ch),
+            ExtendedChar::StartOfInput | ExtendedChar::EndOfInput => false,
+        }
+    }
+
+    /// Whether this label consumes a character, i.e. isn't an anchor.
+    pub fn is_consuming(&self) -> bool {
+        !matches!(self, ExtendedChar::StartOfInput | ExtendedChar::EndOfInput)
+    }
+
+    /// Whether edges with this label can't be looked up by character and
+    /// have to be scanned instead.
+    pub(crate) fn is_scanned(&self) -> bool {
+        matches!(self, ExtendedChar::NoneOf(_) | ExtendedChar::Predicate(_))
+    }
+}
+
+/// The label as drawn by `NFA`'s `Display` and `to_dot`: the character
+/// itself, `.` for the wildcard, `^` and `$` for anchors, `[^...]` for
+/// `NoneOf` and `[:name:]` for predicates.
+impl fmt::Display for ExtendedChar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtendedChar::Char(ch) => write!(f, "{}", ch),
+            ExtendedChar::Wildcard => write!(f, "."),
+            ExtendedChar::StartOfInput => write!(f, "^"),
+            ExtendedChar::EndOfInput => write!(f, "$"),
+            ExtendedChar::NoneOf(excluded) => {
+                write!(f, "[^{}]", excluded.iter().collect::<String>())
+            }
+            ExtendedChar::Predicate(predicate) => write!(f, "[:{}:]", predicate.name()),
+        }
+    }
+}
+
+/// A named test on characters, labeling `ExtendedChar::Predicate` edges.
+///
+/// Closures can't be compared, so a predicate only equals its own clones;
+/// two predicates built separately from the same function are distinct
+/// labels. The name is only used for display and ordering.
+#[derive(Clone)]
+pub struct Predicate {
+    name: Arc<str>,
+    test: Arc<dyn Fn(char) -> bool + Send + Sync>,
+}
+
+impl Predicate {
+    pub fn new(name: &str, test: impl Fn(char) -> bool + Send + Sync + 'static) -> Predicate {
+        Predicate {
+            name: name.into(),
+            test: Arc::new(test),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn test(&self, ch: char) -> bool {
+        (self.test)(ch)
+    }
+
+    /// Holds where both `self` and `other` do.
+    pub fn and(&self, other: &Predicate) -> Predicate {
+        let (first, second) = (self.clone(), other.clone());
+        let name = format!("{}&{}", self.name, other.name);
+        Predicate::new(&name, move |ch| first.test(ch) && second.test(ch))
+    }
+
+    fn address(&self) -> *const () {
+        Arc::as_ptr(&self.test) as *const ()
+    }
+}
+
+impl PartialEq for Predicate {
+    fn eq(&self, other: &Predicate) -> bool {
+        std::ptr::eq(self.address(), other.address())
+    }
+}
+
+impl Eq for Predicate {}
+
+impl Hash for Predicate {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state);
+    }
+}
+
+impl PartialOrd for Predicate {
+    fn partial_cmp(&self, other: &Predicate) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Predicate {
+    fn cmp(&self, other: &Predicate) -> std::cmp::Ordering {
+        (&self.name, self.address()).cmp(&(&other.name, other.address()))
+    }
+}
+
+impl fmt::Debug for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Predicate({})", self.name)
+    }
 }
 
 impl NFA {
-    pub fn is_match(&self, stream: &mut CharStream) -> bool {
-        let mut nodes: HashSet<Node> = self.starting.clone();
-        for ch in stream {
-            let mut new_nodes: HashSet<Node> = HashSet::new();
-            for &node in nodes.iter() {
-                if let Some(set) = self.delta.get(&(node, ExtendedChar::Char(ch))) {
-                    for &new_node in set.iter() {
-                        new_nodes.insert(new_node);
+    pub fn num_states(&self) -> usize {
+        self.states
+    }
+
+    /// The number of labeled edges, counting one per target node. Epsilon
+    /// edges are not included.
+    pub fn num_transitions(&self) -> usize {
+        self.delta.values().map(HashSet::len).sum()
+    }
+
+    /// `num_transitions` divided by the number of ordered pairs of nodes, or
+    /// 0 without nodes. Pairs joined by several labels count once per label,
+    /// so this can exceed 1.
+    pub fn density(&self) -> f64 {
+        if self.states == 0 {
+            return 0.0;
+        }
+        self.num_transitions() as f64 / (self.states as f64 * self.states as f64)
+    }
+
+    pub fn starting_states(&self) -> impl Iterator<Item = Node> + '_ {
+        self.starting.iter().copied()
+    }
+
+    /// Every labeled edge as `(from, label, to)`. The label is an
+    /// `ExtendedChar` rather than a `char` so wildcard edges are included.
+    pub fn transitions(&self) -> impl Iterator<Item = (Node, ExtendedChar, Node)> + '_ {
+        self.delta
+            .iter()
+            .flat_map(|((from, ec), set)| set.iter().map(move |&to| (*from, ec.clone(), to)))
+    }
+
+    /// Every epsilon edge as `(from, to)`.
+    pub fn epsilon_transitions(&self) -> impl Iterator<Item = (Node, Node)> + '_ {
+        self.epsilon
+            .iter()
+            .flat_map(|(&from, set)| set.iter().map(move |&to| (from, to)))
+    }
+
+    pub fn is_final(&self, n: Node) -> bool {
+        self.finished.contains(&n)
+    }
+
+    /// Whether every input has at most one path through the automaton: there
+    /// is exactly one starting node, no epsilon or anchor edge, at most one
+    /// wildcard, `NoneOf` or `Predicate` edge per node, which must not
+    /// consume any of the node's exact characters, and every label leads to
+    /// at most one node.
+    /// `canonicalize` always produces such an automaton.
+    pub fn is_deterministic(&self) -> bool {
+        let mut exact: HashMap<Node, Vec<char>> = HashMap::new();
+        let mut others: HashMap<Node, &ExtendedChar> = HashMap::new();
+        for (node, ec) in self.delta.keys() {
+            match ec {
+                ExtendedChar::Char(ch) => exact.entry(*node).or_default().push(*ch),
+                ExtendedChar::Wildcard | ExtendedChar::NoneOf(_) | ExtendedChar::Predicate(_) => {
+                    if others.insert(*node, ec).is_some() {
+                        return false;
+                    }
+                }
+                ExtendedChar::StartOfInput | ExtendedChar::EndOfInput => return false,
+            }
+        }
+        self.starting.len() == 1
+            && self.epsilon.values().all(HashSet::is_empty)
+            && self.delta.values().all(|set| set.len() <= 1)
+            && others.iter().all(|(node, ec)| {
+                exact
+                    .get(node)
+                    .into_iter()
+                    .flatten()
+                    .all(|&ch| !ec.matches(ch))
+            })
+    }
+
+    /// Whether the empty string is accepted. Epsilon edges (and both anchors)
+    /// are followed from the starting nodes, so this also holds when no
+    /// starting node is itself finished.
+    pub fn accepts_empty(&self) -> bool {
+        self.accepts_at(&self.closure_at(&self.starting, true, false), true, true)
+    }
+
+    /// Whether some edge is labeled with a `Predicate`.
+    pub fn has_predicates(&self) -> bool {
+        self.delta
+            .keys()
+            .any(|(_, ec)| matches!(ec, ExtendedChar::Predicate(_)))
+    }
+
+    /// Every character labeling an edge in `delta`, including the ones a
+    /// `NoneOf` edge excludes. Wildcard and anchor edges contribute nothing.
+    pub fn alphabet(&self) -> HashSet<char> {
+        self.delta
+            .keys()
+            .flat_map(|(_, ec)| match ec {
+                ExtendedChar::Char(ch) => std::slice::from_ref(ch),
+                ExtendedChar::NoneOf(excluded) => &excluded[..],
+                _ => &[],
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Simulates the automaton over `string` one character at a time, tracking
+    /// the epsilon closure of the reachable nodes. Returns `true` iff a
+    /// finished node is reachable once the input is exhausted, so the match is
+    /// anchored at both ends; this is the same as `is_full_match`.
+    pub fn is_match(&self, string: &str) -> bool {
+        self.matches_stream(CharStream::from(string))
+    }
+
+    /// Whether the whole of `s` is accepted. Equivalent to `is_match`.
+    pub fn is_full_match(&self, s: &str) -> bool {
+        self.is_match(s)
+    }
+
+    /// Like `is_match`, but on failure reports how far `s` got: `Err(i)` for
+    /// the byte offset of the first character after which no node is live,
+    /// or `Err(s.len())` if all of `s` is consumed without accepting.
+    pub fn match_with_trace(&self, s: &str) -> Result<(), usize> {
+        let mut stepper = Stepper::new(self);
+        let mut active = stepper.start(true);
+        for (i, ch) in s.char_indices() {
+            active = stepper.step(&active, ch);
+            if active.is_empty() {
+                return Err(i);
+            }
+        }
+        if stepper.accepts(&active, s.is_empty(), true) {
+            Ok(())
+        } else {
+            Err(s.len())
+        }
+    }
+
+    /// Whether some prefix of `s`, possibly empty, is accepted. An
+    /// `end_anchor` only holds for the prefix that is all of `s`.
+    pub fn is_partial_match(&self, s: &str) -> bool {
+        let mut stepper = Stepper::new(self);
+        let mut active = stepper.start(true);
+        if stepper.accepts(&active, true, s.is_empty()) {
+            return true;
+        }
+        for (i, ch) in s.char_indices() {
+            active = stepper.step(&active, ch);
+            if stepper.accepts(&active, false, i + ch.len_utf8() == s.len()) {
+                return true;
+            }
+            if active.is_empty() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Like `is_match`, but feeds characters from `chars` one at a time so the
+    /// input never has to be held in memory. Stops reading as soon as no node
+    /// is active any more.
+    pub fn matches_stream<I: Iterator<Item = char>>(&self, chars: I) -> bool {
+        let mut stepper = Stepper::new(self);
+        let mut active = stepper.start(true);
+        let mut at_start = true;
+        for ch in chars {
+            active = stepper.step(&active, ch);
+            at_start = false;
+            if active.is_empty() {
+                return false;
+            }
+        }
+        stepper.accepts(&active, at_start, true)
+    }
+
+    /// Like `is_match`, but reads UTF-8 input from `reader` a buffer at a
+    /// time, so large files never have to be held in memory. A character
+    /// split across two reads is decoded once its last byte arrives. Invalid
+    /// or truncated UTF-8 is an `InvalidData` error; like `matches_stream`,
+    /// reading stops as soon as no node is active any more.
+    pub fn is_match_reader<R: io::Read>(&self, reader: R) -> io::Result<bool> {
+        let mut chars = ReaderChars {
+            reader,
+            buffer: [0; 8192],
+            pending: Vec::new(),
+            at: 0,
+            decoded: 0,
+            error: None,
+        };
+        let matched = self.matches_stream(&mut chars);
+        match chars.error {
+            Some(error) => Err(error),
+            None => Ok(matched),
+        }
+    }
+
+    /// Like `is_match`, but gives up once more than `max_steps` nodes have
+    /// been expanded, counting every active node at every character, so
+    /// roughly the length of `s` times the number of active nodes. This
+    /// bounds the time spent on untrusted patterns and inputs.
+    pub fn is_match_bounded(&self, s: &str, max_steps: usize) -> Result<bool, BudgetExceeded> {
+        let mut stepper = Stepper::new(self);
+        let mut active = stepper.start(true);
+        let mut steps = 0usize;
+        for ch in s.chars() {
+            steps = steps.saturating_add(active.len());
+            if steps > max_steps {
+                return Err(BudgetExceeded { max_steps });
+            }
+            active = stepper.step(&active, ch);
+            if active.is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(stepper.accepts(&active, s.is_empty(), true))
+    }
+
+    /// Like `is_match`, but applies the limits in `config` and fails instead of
+    /// exceeding them, for patterns and inputs that can't be trusted.
+    pub fn is_match_with(&self, s: &str, config: &MatchConfig) -> Result<bool, MatchError> {
+        let check = |active: &Vec<usize>| match config.max_states_tracked {
+            Some(limit) if active.len() > limit => Err(MatchError::TooManyStates { limit }),
+            _ => Ok(()),
+        };
+        let mut stepper = Stepper::new(self);
+        let mut active = stepper.start(true);
+        check(&active)?;
+        for ch in s.chars() {
+            active = stepper.step(&active, ch);
+            check(&active)?;
+            if active.is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(stepper.accepts(&active, s.is_empty(), true))
+    }
+
+    /// Which alternative accepts all of `s`, when several might, with
+    /// leftmost-first priority. `plus` and `union` record which alternative
+    /// each starting node comes from, numbering those of nested `plus`
+    /// operands in order, so the index returned is the position of the first
+    /// alternative that matches in the flattened chain. Automata that aren't
+    /// an alternation have the single alternative 0. `None` if `s` isn't
+    /// accepted at all.
+    ///
+    /// Only an outermost alternation is tracked: `times` and everything built
+    /// on it forget the alternatives of their operands, so `(a|ab)c` is a
+    /// single alternative and reports 0 on `abc` even though `ab` is the one
+    /// that matched. Distribute the concatenation (`ac|abc`) to tell them
+    /// apart.
+    pub fn match_branch(&self, s: &str) -> Option<usize> {
+        let scanned = self.scanned_edges();
+        self.starting
+            .iter()
+            .filter(|&&start| {
+                let mut nodes = self.closure_at(&[start].into(), true, false);
+                for ch in s.chars() {
+                    nodes = self.step_set(&nodes, ch, &scanned);
+                }
+                self.accepts_at(&nodes, s.is_empty(), true)
+            })
+            .map(|start| self.branches.get(start).copied().unwrap_or(0))
+            .min()
+    }
+
+    /// Like `is_match`, for input that is already decoded into characters.
+    pub fn is_match_chars(&self, chars: &[char]) -> bool {
+        self.matches_stream(chars.iter().copied())
+    }
+
+    /// The nodes active once all of `s` has been consumed, epsilon closure
+    /// included, without checking acceptance. End anchors are not followed,
+    /// since `s` might be followed by more input.
+    pub fn run(&self, s: &str) -> HashSet<Node> {
+        let mut state = self.start_state();
+        for ch in s.chars() {
+            state.step(ch);
+        }
+        state.nodes
+    }
+
+    /// The label `labeled` attached to a finished node reached once all of
+    /// `s` is consumed, or `None` if no labeled finished node is reached.
+    /// When several are, the smallest label wins, so a scanner built with
+    /// `union` gives earlier token ids priority by numbering them lower.
+    pub fn which_match(&self, s: &str) -> Option<u32> {
+        let mut state = self.start_state();
+        for ch in s.chars() {
+            state.step(ch);
+        }
+        self.closure_at(&state.nodes, state.at_start, true)
+            .iter()
+            .filter(|node| self.finished.contains(node))
+            .filter_map(|node| self.labels.get(node))
+            .min()
+            .copied()
+    }
+
+    /// A resumable match positioned before any input has been consumed.
+    pub fn start_state(&self) -> MatchState<'_> {
+        MatchState {
+            nfa: self,
+            scanned: self.scanned_edges(),
+            nodes: self.closure_at(&self.starting, true, false),
+            at_start: true,
+        }
+    }
+
+    /// Returns the byte range of the leftmost substring of `s` accepted by the
+    /// automaton. Among matches starting at that position the longest one is
+    /// reported, so `a*` finds a zero-width match at 0 in `"baa"`.
+    ///
+    /// Anchors refer to the whole of `s`, not to the candidate substring: a
+    /// `start_anchor` only lets a match begin at offset 0 and an `end_anchor`
+    /// forces it to reach the end of `s`. The same holds for `find_all`.
+    pub fn find(&self, s: &str) -> Option<(usize, usize)> {
+        self.find_from(&mut Stepper::new(self), s, 0, MatchPolicy::Greedy)
+    }
+
+    /// Like `find`, but `policy` picks which of the matches starting at the
+    /// leftmost position is reported: the longest for `Greedy`, as `find`
+    /// does, or the shortest for `Lazy`, as with `*?` in other engines.
+    pub fn find_with(&self, s: &str, policy: MatchPolicy) -> Option<(usize, usize)> {
+        self.find_from(&mut Stepper::new(self), s, 0, policy)
+    }
+
+    /// Byte length of the longest accepted prefix of `s`. Unlike `find`, the
+    /// match is anchored at the start of `s`.
+    pub fn longest_accepting_prefix(&self, s: &str) -> Option<usize> {
+        self.match_at(&mut Stepper::new(self), s, 0, MatchPolicy::Greedy)
+    }
+
+    /// End offset of the longest match starting exactly at byte offset
+    /// `start` of `s`, the step `find` repeats at every offset. As with
+    /// `find`, anchors refer to the whole of `s`. Panics if `start` is not a
+    /// char boundary of `s`.
+    pub fn matches_at(&self, s: &str, start: usize) -> Option<usize> {
+        assert!(
+            s.is_char_boundary(start),
+            "byte offset {} is not a char boundary of {:?}",
+            start,
+            s
+        );
+        self.match_at(&mut Stepper::new(self), s, start, MatchPolicy::Greedy)
+    }
+
+    /// Returns every non-overlapping leftmost-longest match in order. After a
+    /// match ending at `e` the search resumes at `e`, or one character later if
+    /// the match was zero-width, so the scan always makes progress.
+    pub fn find_all(&self, s: &str) -> Vec<(usize, usize)> {
+        let mut stepper = Stepper::new(self);
+        let mut matches = Vec::new();
+        let mut from = Some(0);
+        while let Some((start, end)) =
+            from.and_then(|from| self.find_from(&mut stepper, s, from, MatchPolicy::Greedy))
+        {
+            matches.push((start, end));
+            from = resume_after(s, start, end);
+        }
+        matches
+    }
+
+    /// Every match, overlapping or not: for each start offset in order, each
+    /// end offset at which the automaton accepts, in increasing order. Empty
+    /// matches are included.
+    pub fn find_overlapping(&self, s: &str) -> Vec<(usize, usize)> {
+        let mut stepper = Stepper::new(self);
+        let mut matches = Vec::new();
+        for start in s.char_indices().map(|(i, _)| i).chain([s.len()]) {
+            let mut active = stepper.start(start == 0);
+            if stepper.accepts(&active, start == 0, start == s.len()) {
+                matches.push((start, start));
+            }
+            for (i, ch) in s[start..].char_indices() {
+                active = stepper.step(&active, ch);
+                if active.is_empty() {
+                    break;
+                }
+                let at = start + i + ch.len_utf8();
+                if stepper.accepts(&active, false, at == s.len()) {
+                    matches.push((start, at));
+                }
+            }
+        }
+        matches
+    }
+
+    /// Iterates over the slices of `s` at the matches reported by `find_all`.
+    /// Unmatched regions between matches are skipped, and zero-width matches
+    /// yield empty slices.
+    pub fn tokens<'a>(&'a self, s: &'a str) -> Tokens<'a> {
+        Tokens {
+            nfa: self,
+            stepper: Stepper::new(self),
+            s,
+            from: Some(0),
+        }
+    }
+
+    /// Copies `s` with every match reported by `find_all` replaced by
+    /// `replacement`. A zero-width match inserts `replacement` once at its
+    /// position, and the scan then moves on past the next character.
+    pub fn replace_all(&self, s: &str, replacement: &str) -> String {
+        let mut result = String::with_capacity(s.len());
+        let mut copied = 0;
+        for (start, end) in self.find_all(s) {
+            result.push_str(&s[copied..start]);
+            result.push_str(replacement);
+            copied = end;
+        }
+        result.push_str(&s[copied..]);
+        result
+    }
+
+    /// The pieces of `s` around the matches reported by `find_all`, like
+    /// `str::split` with the automaton as delimiter. A match at either end of
+    /// `s` produces an empty first or last piece, and adjacent matches
+    /// produce an empty piece between them. Zero-width matches split between
+    /// characters, at the ends of `s` included.
+    pub fn split<'a>(&self, s: &'a str) -> Vec<&'a str> {
+        let mut pieces = Vec::new();
+        let mut copied = 0;
+        for (start, end) in self.find_all(s) {
+            pieces.push(&s[copied..start]);
+            copied = end;
+        }
+        pieces.push(&s[copied..]);
+        pieces
+    }
+
+    fn find_from(
+        &self,
+        stepper: &mut Stepper,
+        s: &str,
+        from: usize,
+        policy: MatchPolicy,
+    ) -> Option<(usize, usize)> {
+        s[from..]
+            .char_indices()
+            .map(|(i, _)| from + i)
+            .chain([s.len()])
+            .find_map(|start| {
+                self.match_at(stepper, s, start, policy)
+                    .map(|end| (start, end))
+            })
+    }
+
+    /// End offset of the longest (`Greedy`) or shortest (`Lazy`) match of the
+    /// automaton starting at byte offset `start` of `s`.
+    fn match_at(
+        &self,
+        stepper: &mut Stepper,
+        s: &str,
+        start: usize,
+        policy: MatchPolicy,
+    ) -> Option<usize> {
+        let mut active = stepper.start(start == 0);
+        let mut end = None;
+        if stepper.accepts(&active, start == 0, start == s.len()) {
+            end = Some(start);
+        }
+        for (i, ch) in s[start..].char_indices() {
+            if end.is_some() && policy == MatchPolicy::Lazy {
+                break;
+            }
+            active = stepper.step(&active, ch);
+            if active.is_empty() {
+                break;
+            }
+            let at = start + i + ch.len_utf8();
+            if stepper.accepts(&active, false, at == s.len()) {
+                end = Some(at);
+            }
+        }
+        end
+    }
+
+    /// Recognizes the reversal of the language: starting and finished nodes
+    /// swap roles and every edge, including epsilon edges, points backwards.
+    /// Start and end anchors swap as well.
+    pub fn reverse(&self) -> NFA {
+        let mut delta: HashMap<(Node, ExtendedChar), HashSet<Node>> = HashMap::new();
+        for (&(from, ref ec), set) in self.delta.iter() {
+            let ec = match ec {
+                ExtendedChar::StartOfInput => ExtendedChar::EndOfInput,
+                ExtendedChar::EndOfInput => ExtendedChar::StartOfInput,
+                ec => ec.clone(),
+            };
+            for &to in set.iter() {
+                delta.entry((to, ec.clone())).or_default().insert(from);
+            }
+        }
+        let mut epsilon: HashMap<Node, HashSet<Node>> = HashMap::new();
+        for (&from, set) in self.epsilon.iter() {
+            for &to in set.iter() {
+                epsilon.entry(to).or_default().insert(from);
+            }
+        }
+
+        NFA {
+            states: self.states,
+            starting: self.finished.clone(),
+            delta,
+            epsilon,
+            finished: self.starting.clone(),
+            labels: [].into(),
+            branches: [].into(),
+        }
+    }
+
+    /// Number of distinct accepting paths for `s`, saturating at `usize::MAX`.
+    /// A path is a sequence of character-consuming transitions; the epsilon
+    /// moves between two of them are not distinguished, so constructions that
+    /// only differ in how they splice epsilon edges don't inflate the count.
+    pub fn count_accepting_paths(&self, s: &str) -> usize {
+        let closures: HashMap<Node, HashSet<Node>> = (0..self.states)
+            .map(|n| (Node(n), self.epsilon_closure(&[Node(n)].into())))
+            .collect();
+        let mut outgoing: HashMap<Node, Vec<(&ExtendedChar, &HashSet<Node>)>> = HashMap::new();
+        for ((node, ec), set) in self.delta.iter() {
+            outgoing.entry(*node).or_default().push((ec, set));
+        }
+        let initial = self.closure_at(&self.starting, true, false);
+
+        // `None` stands for the position before any input, whose epsilon
+        // closure is that of all starting nodes together.
+        let mut counts: HashMap<Option<Node>, usize> = [(None, 1)].into();
+        for ch in s.chars() {
+            let mut next: HashMap<Option<Node>, usize> = HashMap::new();
+            for (&at, &count) in counts.iter() {
+                let closure = match at {
+                    Some(node) => &closures[&node],
+                    None => &initial,
+                };
+                for node in closure.iter() {
+                    for &(ec, targets) in outgoing.get(node).into_iter().flatten() {
+                        if !ec.matches(ch) {
+                            continue;
+                        }
+                        for &target in targets.iter() {
+                            let entry = next.entry(Some(target)).or_default();
+                            *entry = entry.saturating_add(count);
+                        }
+                    }
+                }
+            }
+            counts = next;
+        }
+        counts
+            .iter()
+            .filter(|(&at, _)| match at {
+                Some(node) => self.accepts_at(&closures[&node], false, true),
+                None => self.accepts_at(&initial, true, true),
+            })
+            .fold(0, |total: usize, (_, &count)| total.saturating_add(count))
+    }
+
+    /// Every accepted string of at most `max_len` characters, shortest first
+    /// and lexicographically within a length. Strings are built from the
+    /// characters appearing on `delta` edges; wildcard edges only contribute
+    /// those characters. The output can grow exponentially with `max_len`, so
+    /// keep it small.
+    pub fn enumerate(&self, max_len: usize) -> Vec<String> {
+        let mut alphabet: Vec<char> = self.alphabet().into_iter().collect();
+        alphabet.sort();
+
+        let scanned = &self.scanned_edges();
+        let mut accepted = Vec::new();
+        let mut frontier = vec![(String::new(), self.closure_at(&self.starting, true, false))];
+        for len in 0..=max_len {
+            for (string, nodes) in frontier.iter() {
+                if self.accepts_at(nodes, len == 0, true) {
+                    accepted.push(string.clone());
+                }
+            }
+            if len == max_len {
+                break;
+            }
+            frontier = frontier
+                .iter()
+                .flat_map(|(string, nodes)| {
+                    alphabet.iter().map(move |&ch| {
+                        (
+                            format!("{}{}", string, ch),
+                            self.step_set(nodes, ch, scanned),
+                        )
+                    })
+                })
+                .filter(|(_, nodes)| !nodes.is_empty())
+                .collect();
+        }
+        accepted
+    }
+
+    /// A shortest accepted string, `Some("")` if the empty string is accepted
+    /// and `None` if nothing is. Wildcard, `NoneOf` and `Predicate` edges are
+    /// spelled with the first character they consume out of `alphabet` in
+    /// order, then `a` to `z`, `0` to `9` and finally every character. Ties
+    /// are broken the same way on every call.
+    pub fn shortest_match(&self) -> Option<String> {
+        // a node, whether a character was consumed and whether an end anchor
+        // was passed, which rule out start anchors and any further input
+        type State = (Node, bool, bool);
+        let mut alphabet: Vec<char> = self.alphabet().into_iter().collect();
+        alphabet.sort();
+        let witness = |ec: &ExtendedChar| {
+            let mut candidates = alphabet
+                .iter()
+                .copied()
+                .chain('a'..='z')
+                .chain('0'..='9')
+                .chain(char::MIN..=char::MAX);
+            candidates.find(|&ch| ec.matches(ch))
+        };
+        let mut outgoing: HashMap<Node, Vec<(ExtendedChar, Node)>> = HashMap::new();
+        for (from, ec, to) in self.transitions() {
+            outgoing.entry(from).or_default().push((ec, to));
+        }
+        for edges in outgoing.values_mut() {
+            edges.sort();
+        }
+        // the state each one was first reached from, and the character read
+        let mut parent: HashMap<State, Option<(State, Option<char>)>> = HashMap::new();
+        let moves = |(node, consumed, ended): State| -> Vec<(State, Option<char>)> {
+            let mut epsilon: Vec<Node> = self
+                .epsilon
+                .get(&node)
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect();
+            epsilon.sort();
+            let mut moves: Vec<(State, Option<char>)> = epsilon
+                .into_iter()
+                .map(|to| ((to, consumed, ended), None))
+                .collect();
+            for (ec, to) in outgoing.get(&node).into_iter().flatten() {
+                let to = *to;
+                match ec {
+                    ExtendedChar::StartOfInput if !consumed => {
+                        moves.push(((to, consumed, ended), None))
+                    }
+                    ExtendedChar::EndOfInput => moves.push(((to, consumed, true), None)),
+                    ExtendedChar::StartOfInput => {}
+                    ec if !ended => {
+                        if let Some(ch) = witness(ec) {
+                            moves.push(((to, true, false), Some(ch)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            moves
+        };
+
+        let mut starting: Vec<Node> = self.starting.iter().copied().collect();
+        starting.sort();
+        let mut layer: Vec<State> = starting
+            .into_iter()
+            .map(|node| (node, false, false))
+            .collect();
+        for &state in layer.iter() {
+            parent.insert(state, None);
+        }
+        // each layer holds the states first reached after reading one more character
+        while !layer.is_empty() {
+            let mut i = 0;
+            while i < layer.len() {
+                for (next, ch) in moves(layer[i]) {
+                    if ch.is_none() && !parent.contains_key(&next) {
+                        parent.insert(next, Some((layer[i], None)));
+                        layer.push(next);
+                    }
+                }
+                i += 1;
+            }
+            if let Some(&found) = layer
+                .iter()
+                .find(|(node, _, _)| self.finished.contains(node))
+            {
+                let mut chars = Vec::new();
+                let mut at = found;
+                while let Some((previous, ch)) = parent[&at] {
+                    chars.extend(ch);
+                    at = previous;
+                }
+                return Some(chars.into_iter().rev().collect());
+            }
+            let mut next_layer = Vec::new();
+            for &state in layer.iter() {
+                for (next, ch) in moves(state) {
+                    if ch.is_some() && !parent.contains_key(&next) {
+                        parent.insert(next, Some((state, ch)));
+                        next_layer.push(next);
                     }
                 }
-                if let Some(set) = self.delta.get(&(node, ExtendedChar::Wildcard)) {
-                    for &new_node in set.iter() {
-                        new_nodes.insert(new_node);
+            }
+            layer = next_layer;
+        }
+        None
+    }
+
+    /// Renders the automaton as a Graphviz digraph, e.g. for `dot -Tpng`.
+    /// Finished nodes are drawn as double circles, each starting node gets an
+    /// incoming arrow from an invisible point, and edges are labeled with the
+    /// character they consume (`.` for the wildcard, `^` and `$` for anchors,
+    /// `ε` for epsilon edges). Like `Display`, everything is written in sorted
+    /// order.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph NFA {\n    rankdir=LR;\n    node [shape=circle];\n");
+        let mut finished: Vec<_> = self.finished.iter().collect();
+        finished.sort();
+        for Node(n) in finished {
+            out.push_str(&format!("    {} [shape=doublecircle];\n", n));
+        }
+        let mut starting: Vec<_> = self.starting.iter().collect();
+        starting.sort();
+        for Node(n) in starting {
+            out.push_str(&format!(
+                "    start{} [shape=point];\n    start{} -> {};\n",
+                n, n, n
+            ));
+        }
+        let mut edges: Vec<(Node, String, Node)> = Vec::new();
+        for (&(from, ref ec), set) in self.delta.iter() {
+            let label: String = ec
+                .to_string()
+                .chars()
+                .map(|ch| match ch {
+                    '"' | '\\' => format!("\\{}", ch),
+                    ch => ch.to_string(),
+                })
+                .collect();
+            edges.extend(set.iter().map(|&to| (from, label.clone(), to)));
+        }
+        for (&from, set) in self.epsilon.iter() {
+            edges.extend(set.iter().map(|&to| (from, String::from("ε"), to)));
+        }
+        edges.sort();
+        for (Node(from), label, Node(to)) in edges {
+            out.push_str(&format!("    {} -> {} [label=\"{}\"];\n", from, to, label));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Whether the automaton accepts no string at all, i.e. no path from a
+    /// starting node to a finished node can be followed on any input. Every
+    /// consuming edge is taken to match some character, but anchors only hold
+    /// where they assert: a start anchor before anything is consumed, and
+    /// nothing is consumed after an end anchor, so `a$b` is empty.
+    pub fn is_empty_language(&self) -> bool {
+        let mut outgoing: HashMap<Node, Vec<(&ExtendedChar, &HashSet<Node>)>> = HashMap::new();
+        for ((node, ec), set) in self.delta.iter() {
+            outgoing.entry(*node).or_default().push((ec, set));
+        }
+        // a node, whether a character was consumed on the way there and
+        // whether an end anchor was crossed
+        let mut seen: HashSet<(Node, bool, bool)> = self
+            .starting
+            .iter()
+            .map(|&node| (node, false, false))
+            .collect();
+        let mut pending: Vec<(Node, bool, bool)> = seen.iter().copied().collect();
+        while let Some((node, consumed, ended)) = pending.pop() {
+            if self.finished.contains(&node) {
+                return false;
+            }
+            let silent = self
+                .epsilon
+                .get(&node)
+                .into_iter()
+                .flatten()
+                .map(|&next| (next, consumed, ended));
+            let moves = outgoing
+                .get(&node)
+                .into_iter()
+                .flatten()
+                .filter_map(|&(ec, set)| match ec {
+                    ExtendedChar::StartOfInput if consumed => None,
+                    ExtendedChar::StartOfInput => Some((set, consumed, ended)),
+                    ExtendedChar::EndOfInput => Some((set, consumed, true)),
+                    _ if ended => None,
+                    _ => Some((set, true, ended)),
+                })
+                .flat_map(|(set, consumed, ended)| {
+                    set.iter().map(move |&next| (next, consumed, ended))
+                });
+            for config in silent.chain(moves) {
+                if seen.insert(config) {
+                    pending.push(config);
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether infinitely many strings are accepted: some cycle that consumes
+    /// at least one character lies on a path from a starting node to a
+    /// finished node. Cycles made only of epsilon and anchor edges don't count.
+    pub fn is_infinite_language(&self) -> bool {
+        let useful = self.useful();
+        let mut successors: HashMap<Node, Vec<Node>> = HashMap::new();
+        let edges = self
+            .transitions()
+            .map(|(from, _, to)| (from, to))
+            .chain(self.epsilon_transitions());
+        for (from, to) in edges {
+            if useful.contains(&from) && useful.contains(&to) {
+                successors.entry(from).or_default().push(to);
+            }
+        }
+        // a consuming edge from -> to lies on a cycle iff from is reachable from to
+        self.transitions()
+            .filter(|(_, ec, _)| ec.is_consuming())
+            .filter(|(from, _, to)| useful.contains(from) && useful.contains(to))
+            .any(|(from, _, to)| {
+                let mut seen: HashSet<Node> = [to].into();
+                let mut pending = vec![to];
+                while let Some(node) = pending.pop() {
+                    if node == from {
+                        return true;
                     }
+                    for &next in successors.get(&node).into_iter().flatten() {
+                        if seen.insert(next) {
+                            pending.push(next);
+                        }
+                    }
+                }
+                false
+            })
+    }
+
+    /// Removes nodes that are unreachable from the starting nodes or cannot
+    /// reach a finished node, renumbering the rest contiguously from 0 in
+    /// their original order. The language is unchanged.
+    pub fn trim(&self) -> NFA {
+        let mut kept: Vec<Node> = self.useful().into_iter().collect();
+        kept.sort();
+        let renumber: HashMap<Node, Node> = kept
+            .iter()
+            .enumerate()
+            .map(|(i, &node)| (node, Node(i)))
+            .collect();
+        let map = |set: &HashSet<Node>| -> HashSet<Node> {
+            set.iter()
+                .filter_map(|node| renumber.get(node))
+                .copied()
+                .collect()
+        };
+
+        let mut delta = HashMap::new();
+        for (&(node, ref ec), set) in self.delta.iter() {
+            if let Some(&from) = renumber.get(&node) {
+                let targets = map(set);
+                if !targets.is_empty() {
+                    delta.insert((from, ec.clone()), targets);
+                }
+            }
+        }
+        let mut epsilon = HashMap::new();
+        for (node, set) in self.epsilon.iter() {
+            if let Some(&from) = renumber.get(node) {
+                let targets = map(set);
+                if !targets.is_empty() {
+                    epsilon.insert(from, targets);
+                }
+            }
+        }
+
+        NFA {
+            states: kept.len(),
+            starting: map(&self.starting),
+            delta,
+            epsilon,
+            finished: map(&self.finished),
+            labels: self
+                .labels
+                .iter()
+                .filter_map(|(node, &label)| Some((*renumber.get(node)?, label)))
+                .collect(),
+            branches: self
+                .branches
+                .iter()
+                .filter_map(|(node, &branch)| Some((*renumber.get(node)?, branch)))
+                .collect(),
+        }
+    }
+
+    /// The Brzozowski derivative with respect to `ch`: an automaton accepting
+    /// exactly the `w` such that `ch` followed by `w` is accepted. The nodes
+    /// are kept, but the starting nodes are replaced by the ones active after
+    /// reading `ch`, and start anchors are dropped since they can no longer
+    /// hold. Taking one derivative per character and then checking
+    /// `accepts_empty` is the same as `is_match`.
+    pub fn derivative(&self, ch: char) -> NFA {
+        let mut result = self.clone();
+        let start = self.closure_at(&self.starting, true, false);
+        result.starting = self.step_set(&start, ch, &self.scanned_edges());
+        result.branches.clear();
+        result
+            .delta
+            .retain(|(_, ec), _| *ec != ExtendedChar::StartOfInput);
+        result
+    }
+
+    /// Adds a dead node, numbered after the existing ones, and an edge to it
+    /// for every node and character of `alphabet` that no edge of the node
+    /// consumes. The dead node loops to itself on all of `alphabet` and isn't
+    /// finished, so the language is unchanged but every node has a move on
+    /// every character of `alphabet`. Epsilon edges are left as they are.
+    pub fn complete(&self, alphabet: &[char]) -> NFA {
+        let dead = Node(self.states);
+        let mut outgoing: HashMap<Node, Vec<&ExtendedChar>> = HashMap::new();
+        for (node, ec) in self.delta.keys() {
+            outgoing.entry(*node).or_default().push(ec);
+        }
+        let mut delta = self.delta.clone();
+        for node in (0..self.states).map(Node) {
+            let labels = outgoing.get(&node).map(Vec::as_slice).unwrap_or_default();
+            for &ch in alphabet {
+                if !labels.iter().any(|ec| ec.matches(ch)) {
+                    delta.insert((node, ExtendedChar::Char(ch)), [dead].into());
+                }
+            }
+        }
+        for &ch in alphabet {
+            delta.insert((dead, ExtendedChar::Char(ch)), [dead].into());
+        }
+
+        NFA {
+            states: self.states + 1,
+            starting: self.starting.clone(),
+            delta,
+            epsilon: self.epsilon.clone(),
+            finished: self.finished.clone(),
+            labels: self.labels.clone(),
+            branches: self.branches.clone(),
+        }
+    }
+
+    /// Nodes lying on some path from a starting node to a finished node.
+    fn useful(&self) -> HashSet<Node> {
+        self.reachable()
+            .intersection(&self.reverse().reachable())
+            .copied()
+            .collect()
+    }
+
+    /// Nodes reachable from a starting node along any sequence of transitions,
+    /// ignoring which characters they consume.
+    fn reachable(&self) -> HashSet<Node> {
+        let mut successors: HashMap<Node, Vec<Node>> = HashMap::new();
+        for (&(node, _), set) in self.delta.iter() {
+            successors
+                .entry(node)
+                .or_default()
+                .extend(set.iter().copied());
+        }
+        for (&node, set) in self.epsilon.iter() {
+            successors
+                .entry(node)
+                .or_default()
+                .extend(set.iter().copied());
+        }
+        let mut seen = self.starting.clone();
+        let mut pending: Vec<Node> = self.starting.iter().copied().collect();
+        while let Some(node) = pending.pop() {
+            for &next in successors.get(&node).into_iter().flatten() {
+                if seen.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Every node reachable from `nodes` through zero or more epsilon edges.
+    /// Anchor edges are not followed.
+    pub fn epsilon_closure(&self, nodes: &HashSet<Node>) -> HashSet<Node> {
+        self.closure_at(nodes, false, false)
+    }
+
+    /// Like `epsilon_closure`, but also follows `StartOfInput` edges when
+    /// `at_start` and `EndOfInput` edges when `at_end`.
+    pub(crate) fn closure_at(
+        &self,
+        nodes: &HashSet<Node>,
+        at_start: bool,
+        at_end: bool,
+    ) -> HashSet<Node> {
+        let mut closure = nodes.clone();
+        let mut pending: Vec<Node> = nodes.iter().copied().collect();
+        while let Some(node) = pending.pop() {
+            let start = at_start.then(|| self.delta.get(&(node, ExtendedChar::StartOfInput)));
+            let end = at_end.then(|| self.delta.get(&(node, ExtendedChar::EndOfInput)));
+            let targets = [self.epsilon.get(&node), start.flatten(), end.flatten()];
+            for &next in targets.into_iter().flatten().flatten() {
+                if closure.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        closure
+    }
+
+    /// Whether `nodes` accept at a position that is the start and/or the end
+    /// of the input, following the anchor edges that hold there.
+    pub(crate) fn accepts_at(&self, nodes: &HashSet<Node>, at_start: bool, at_end: bool) -> bool {
+        !nodes.is_disjoint(&self.finished)
+            || (at_start || at_end)
+                && !self
+                    .closure_at(nodes, at_start, at_end)
+                    .is_disjoint(&self.finished)
+    }
+
+    /// Indexes the `NoneOf` and `Predicate` edges by node, for `step_set`.
+    /// Callers that step more than once build it once and reuse it.
+    pub(crate) fn scanned_edges(&self) -> ScannedEdges {
+        let mut edges = vec![Vec::new(); self.states];
+        for ((Node(n), ec), set) in self.delta.iter() {
+            if ec.is_scanned() {
+                edges[*n].push((ec.clone(), set.iter().copied().collect()));
+            }
+        }
+        ScannedEdges(edges)
+    }
+
+    /// The epsilon closure of the nodes reachable from `nodes` by consuming
+    /// `ch`. `scanned` is this automaton's `scanned_edges`.
+    pub(crate) fn step_set(
+        &self,
+        nodes: &HashSet<Node>,
+        ch: char,
+        scanned: &ScannedEdges,
+    ) -> HashSet<Node> {
+        let mut new_nodes: HashSet<Node> = HashSet::new();
+        for &node in nodes.iter() {
+            if let Some(set) = self.delta.get(&(node, ExtendedChar::Char(ch))) {
+                for &new_node in set.iter() {
+                    new_nodes.insert(new_node);
                 }
             }
-            nodes = new_nodes;
+            if let Some(set) = self.delta.get(&(node, ExtendedChar::Wildcard)) {
+                for &new_node in set.iter() {
+                    new_nodes.insert(new_node);
+                }
+            }
+            new_nodes.extend(scanned.targets(node, ch));
+        }
+        self.epsilon_closure(&new_nodes)
+    }
+}
+
+/// The `NoneOf` and `Predicate` edges of every node, which can't be looked up
+/// in `delta` by character, by node number.
+#[derive(Debug, Clone)]
+pub(crate) struct ScannedEdges(Vec<Vec<(ExtendedChar, Vec<Node>)>>);
+
+impl ScannedEdges {
+    /// The targets of the scanned edges of `node` that consume `ch`.
+    pub(crate) fn targets(&self, Node(n): Node, ch: char) -> impl Iterator<Item = Node> + '_ {
+        self.0[n]
+            .iter()
+            .filter(move |(ec, _)| ec.matches(ch))
+            .flat_map(|(_, targets)| targets.iter().copied())
+    }
+}
+
+impl fmt::Display for NFA {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |nodes: &HashSet<Node>| {
+            let mut nodes: Vec<Node> = nodes.iter().copied().collect();
+            nodes.sort();
+            let nodes: Vec<String> = nodes.iter().map(Node::to_string).collect();
+            format!("{{{}}}", nodes.join(", "))
+        };
+        writeln!(f, "states: {}", self.states)?;
+        writeln!(f, "start: {}", list(&self.starting))?;
+        write!(f, "final: {}", list(&self.finished))?;
+        if !self.labels.is_empty() {
+            let mut labels: Vec<(&Node, &u32)> = self.labels.iter().collect();
+            labels.sort();
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(node, label)| format!("{}: {}", node, label))
+                .collect();
+            write!(f, "\nlabels: {{{}}}", labels.join(", "))?;
+        }
+
+        // `None` labels an epsilon edge
+        let mut edges: Vec<(Node, Option<ExtendedChar>, &HashSet<Node>)> = self
+            .delta
+            .iter()
+            .map(|(&(from, ref ec), set)| (from, Some(ec.clone()), set))
+            .chain(self.epsilon.iter().map(|(&from, set)| (from, None, set)))
+            .collect();
+        edges.sort_by(|(from, ec, _), (other_from, other_ec, _)| {
+            (from, ec).cmp(&(other_from, other_ec))
+        });
+        for (from, ec, set) in edges {
+            let label = match ec {
+                Some(ec) => ec.to_string(),
+                None => String::from("ε"),
+            };
+            write!(f, "\n{} --{}--> {}", from, label, list(set))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for NFA {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Same as `unit(ExtendedChar::Char(ch))`.
+impl From<char> for NFA {
+    fn from(ch: char) -> NFA {
+        unit(ExtendedChar::Char(ch))
+    }
+}
+
+/// Same as `literal(s)`.
+impl From<&str> for NFA {
+    fn from(s: &str) -> NFA {
+        literal(s)
+    }
+}
+
+/// Concatenates one `unit` per character, the same as `literal`.
+impl FromIterator<char> for NFA {
+    fn from_iter<I: IntoIterator<Item = char>>(chars: I) -> NFA {
+        chars
+            .into_iter()
+            .fold(empty(), |nfa, ch| times_into(nfa, &NFA::from(ch)))
+    }
+}
+
+/// `a + b` is `plus(&a, &b)`, reusing the maps of `a`. As in patterns,
+/// where `|` binds loosest, `+` has lower precedence than `*` (`times`), so
+/// `a * b + c` is `(ab)|c`; `star` remains a function.
+impl ops::Add for NFA {
+    type Output = NFA;
+
+    fn add(self, other: NFA) -> NFA {
+        plus_into(self, &other)
+    }
+}
+
+/// `&a + &b` is `plus(&a, &b)`.
+impl ops::Add for &NFA {
+    type Output = NFA;
+
+    fn add(self, other: &NFA) -> NFA {
+        plus(self, other)
+    }
+}
+
+/// `a * b` is `times(&a, &b)`, reusing the maps of `a`.
+impl ops::Mul for NFA {
+    type Output = NFA;
+
+    fn mul(self, other: NFA) -> NFA {
+        times_into(self, &other)
+    }
+}
+
+/// `&a * &b` is `times(&a, &b)`.
+impl ops::Mul for &NFA {
+    type Output = NFA;
+
+    fn mul(self, other: &NFA) -> NFA {
+        times(self, other)
+    }
+}
+
+/// Which match `NFA::find_with` reports among those starting at the leftmost
+/// position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchPolicy {
+    /// The longest match.
+    #[default]
+    Greedy,
+    /// The shortest match.
+    Lazy,
+}
+
+/// Limits applied by `NFA::is_match_with`. The default has no limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchConfig {
+    /// Largest number of nodes that may be active at once.
+    pub max_states_tracked: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MatchError {
+    /// More than `limit` nodes were active after some prefix of the input.
+    TooManyStates { limit: usize },
+    /// A lock shared by the workers of `NFA::try_is_match_parallel` was
+    /// poisoned by a worker that panicked while holding it.
+    Poisoned,
+    /// A worker of `NFA::try_is_match_parallel` panicked.
+    WorkerPanicked,
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchError::TooManyStates { limit } => {
+                write!(f, "more than {} states active at once", limit)
+            }
+            MatchError::Poisoned => write!(f, "a lock shared by the workers was poisoned"),
+            MatchError::WorkerPanicked => write!(f, "a worker thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+/// Returned by `NFA::is_match_bounded` when matching needs more than
+/// `max_steps` node expansions.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BudgetExceeded {
+    pub max_steps: usize,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "matching took more than {} steps", self.max_steps)
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// The characters of UTF-8 input from a reader, for `NFA::is_match_reader`.
+/// Iteration stops at the end of the input or at the first error, which is
+/// kept in `error`.
+struct ReaderChars<R> {
+    reader: R,
+    buffer: [u8; 8192],
+    // bytes read so far; `pending[at..decoded]` is valid UTF-8 not yet
+    // yielded, and anything after it the start of a split character
+    pending: Vec<u8>,
+    at: usize,
+    decoded: usize,
+    error: Option<io::Error>,
+}
+
+impl<R: io::Read> ReaderChars<R> {
+    /// Reads until `pending` holds at least one whole character not yet
+    /// yielded. Returns false at the end of the input or on an error.
+    fn fill(&mut self) -> bool {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "input is not valid UTF-8");
+        while self.at == self.decoded {
+            self.pending.drain(..self.at);
+            self.at = 0;
+            let read = match self.reader.read(&mut self.buffer) {
+                Ok(0) if self.pending.is_empty() => return false,
+                Ok(0) => {
+                    self.error = Some(invalid());
+                    return false;
+                }
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.error = Some(e);
+                    return false;
+                }
+            };
+            self.pending.extend_from_slice(&self.buffer[..read]);
+            self.decoded = match std::str::from_utf8(&self.pending) {
+                Ok(decoded) => decoded.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => {
+                    self.error = Some(invalid());
+                    return false;
+                }
+            };
+        }
+        true
+    }
+}
+
+impl<R: io::Read> Iterator for ReaderChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if !self.fill() {
+            return None;
+        }
+        let decoded =
+            std::str::from_utf8(&self.pending[self.at..self.decoded]).expect("checked in fill");
+        let ch = decoded.chars().next()?;
+        self.at += ch.len_utf8();
+        Some(ch)
+    }
+}
+
+/// Incremental matching state created by `NFA::start_state`. Characters are
+/// fed with `step` as they arrive and acceptance can be queried at any point.
+#[derive(Debug, Clone)]
+pub struct MatchState<'a> {
+    nfa: &'a NFA,
+    scanned: ScannedEdges,
+    nodes: HashSet<Node>,
+    at_start: bool,
+}
+
+impl MatchState<'_> {
+    pub fn step(&mut self, ch: char) {
+        self.nodes = self.nfa.step_set(&self.nodes, ch, &self.scanned);
+        self.at_start = false;
+    }
+
+    /// Whether the input consumed so far is accepted, treating the current
+    /// position as the end of the input.
+    pub fn is_accepting(&self) -> bool {
+        self.nfa.accepts_at(&self.nodes, self.at_start, true)
+    }
+}
+
+/// Iterator returned by `NFA::tokens`.
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    nfa: &'a NFA,
+    stepper: Stepper<'a>,
+    s: &'a str,
+    from: Option<usize>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let (start, end) = self.nfa.find_from(
+            &mut self.stepper,
+            self.s,
+            self.from.take()?,
+            MatchPolicy::Greedy,
+        )?;
+        self.from = resume_after(self.s, start, end);
+        Some(&self.s[start..end])
+    }
+}
+
+/// Where a scan resumes after the match `start..end`: at `end`, or one
+/// character later for a zero-width match, or nowhere at the end of `s`.
+fn resume_after(s: &str, start: usize, end: usize) -> Option<usize> {
+    if start != end {
+        return Some(end);
+    }
+    s[end..].chars().next().map(|ch| end + ch.len_utf8())
+}
+
+pub fn plus(first: &NFA, second: &NFA) -> NFA {
+    plus_into(with_room_for(first, second), second)
+}
+
+pub fn times(first: &NFA, second: &NFA) -> NFA {
+    times_into(with_room_for(first, second), second)
+}
+
+/// `times`, together with where each node of the result came from. The
+/// automaton is identical to `times(first, second)`.
+pub fn times_traced(first: &NFA, second: &NFA) -> (NFA, StateProvenance) {
+    let nfa = times(first, second);
+    let origins = (0..first.states)
+        .map(|n| (0, Node(n)))
+        .chain((0..second.states).map(|n| (1, Node(n))))
+        .collect();
+    (nfa, StateProvenance { origins })
+}
+
+/// Maps each node of a composed automaton back to the operand it was copied
+/// from, as `(operand_index, original_node)`, for debugging. Created by
+/// `times_traced`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateProvenance {
+    // indexed by node of the result
+    origins: Vec<(usize, Node)>,
+}
+
+impl StateProvenance {
+    /// The operand index (0 for the first) and node of that operand that
+    /// `node` was copied from, or `None` if `node` is out of bounds.
+    pub fn origin(&self, Node(n): Node) -> Option<(usize, Node)> {
+        self.origins.get(n).copied()
+    }
+}
+
+/// `plus` reusing the maps of an owned `first`.
+fn plus_into(mut first: NFA, second: &NFA) -> NFA {
+    // the alternatives of `second` are numbered after those of `first`
+    if first.branches.is_empty() {
+        first.branches = first.starting.iter().map(|&node| (node, 0)).collect();
+    }
+    let after = first
+        .branches
+        .values()
+        .max()
+        .map_or(0, |&branch| branch + 1);
+    let offset = append(&mut first, second);
+    let increase = shifted(offset);
+    first.starting.extend(second.starting.iter().map(increase));
+    first.branches.extend(second.starting.iter().map(|node| {
+        (
+            increase(node),
+            after + second.branches.get(node).copied().unwrap_or(0),
+        )
+    }));
+    first.finished.extend(second.finished.iter().map(increase));
+    first
+}
+
+/// `times` reusing the maps of an owned `first`.
+fn times_into(mut first: NFA, second: &NFA) -> NFA {
+    let offset = append(&mut first, second);
+    let increase = shifted(offset);
+    // every first.finished node moves silently into second's starting nodes
+    let second_starting: HashSet<Node> = second.starting.iter().map(increase).collect();
+    for &node in first.finished.iter() {
+        first
+            .epsilon
+            .entry(node)
+            .or_default()
+            .extend(second_starting.iter().copied());
+    }
+    first.finished = second.finished.iter().map(increase).collect();
+    first.branches.clear();
+    first
+}
+
+/// A copy of `nfa` whose maps are allocated with room for the transitions of
+/// `other` as well, so that appending it doesn't rehash.
+fn with_room_for(nfa: &NFA, other: &NFA) -> NFA {
+    let mut delta = HashMap::with_capacity(nfa.delta.len() + other.delta.len());
+    delta.extend(
+        nfa.delta
+            .iter()
+            .map(|(key, set)| (key.clone(), set.clone())),
+    );
+    let mut epsilon =
+        HashMap::with_capacity(nfa.epsilon.len() + other.epsilon.len() + nfa.finished.len());
+    epsilon.extend(nfa.epsilon.iter().map(|(&node, set)| (node, set.clone())));
+    NFA {
+        states: nfa.states,
+        starting: nfa.starting.clone(),
+        delta,
+        epsilon,
+        finished: nfa.finished.clone(),
+        labels: nfa.labels.clone(),
+        branches: nfa.branches.clone(),
+    }
+}
+
+/// Renumbers a node by `offset`, panicking rather than wrapping on overflow.
+fn shifted(offset: usize) -> impl Fn(&Node) -> Node + Copy {
+    move |&Node(n)| {
+        Node(
+            n.checked_add(offset)
+                .expect("renumbered node overflows usize"),
+        )
+    }
+}
+
+/// Adds the nodes and transitions of `other` to `nfa`, numbered after the
+/// existing ones, and returns the offset they were moved by. Starting and
+/// finished nodes are left to the caller. Panics rather than wrapping if the
+/// combined state count, or a renumbered node, overflows `usize`.
+fn append(nfa: &mut NFA, other: &NFA) -> usize {
+    let offset = nfa.states;
+    let states = offset
+        .checked_add(other.states)
+        .unwrap_or_else(|| panic!("{} + {} states overflows usize", offset, other.states));
+    let increase = shifted(offset);
+    nfa.delta.reserve(other.delta.len());
+    nfa.delta
+        .extend(other.delta.iter().map(|((node, ec), set)| {
+            (
+                (increase(node), ec.clone()),
+                set.iter().map(increase).collect(),
+            )
+        }));
+    nfa.epsilon.reserve(other.epsilon.len());
+    nfa.epsilon.extend(
+        other
+            .epsilon
+            .iter()
+            .map(|(node, set)| (increase(node), set.iter().map(increase).collect())),
+    );
+    nfa.labels.extend(
+        other
+            .labels
+            .iter()
+            .map(|(node, &label)| (increase(node), label)),
+    );
+    nfa.states = states;
+    offset
+}
+
+pub fn unit(ec: ExtendedChar) -> NFA {
+    NFA {
+        states: 2,
+        starting: [Node(0)].into(),
+        delta: [((Node(0), ec), [Node(1)].into())].into(),
+        epsilon: [].into(),
+        finished: [Node(1)].into(),
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// A zero-width assertion that holds only before the first character of the
+/// input, for `^`.
+pub fn start_anchor() -> NFA {
+    unit(ExtendedChar::StartOfInput)
+}
+
+/// A zero-width assertion that holds only after the last character of the
+/// input, for `$`.
+pub fn end_anchor() -> NFA {
+    unit(ExtendedChar::EndOfInput)
+}
+
+/// Any single character, i.e. `.`.
+pub fn dot() -> NFA {
+    unit(ExtendedChar::Wildcard)
+}
+
+/// Any single character inside one of the inclusive `ranges`, with one
+/// transition per character.
+pub fn class(ranges: &[(char, char)]) -> NFA {
+    let chars = ranges.iter().flat_map(|&(low, high)| low..=high);
+    single_of(chars)
+}
+
+/// Any single character of `alphabet` outside all of the inclusive `ranges`.
+pub fn negated_class(ranges: &[(char, char)], alphabet: &[char]) -> NFA {
+    let in_ranges = |ch: char| ranges.iter().any(|&(low, high)| low <= ch && ch <= high);
+    single_of(alphabet.iter().copied().filter(|&ch| !in_ranges(ch)))
+}
+
+/// Any single character not in `excluded`, as one `NoneOf` edge, so unlike
+/// `negated_class` no alphabet has to be enumerated.
+pub fn none_of(excluded: &[char]) -> NFA {
+    let mut excluded = excluded.to_vec();
+    excluded.sort();
+    excluded.dedup();
+    unit(ExtendedChar::NoneOf(excluded))
+}
+
+/// Any single character `predicate` holds for, as one `Predicate` edge that
+/// is consulted alongside any exact edges. Every call creates a new label,
+/// drawn as `[:matching:]`.
+pub fn matching<F: Fn(char) -> bool + Send + Sync + 'static>(predicate: F) -> NFA {
+    unit(ExtendedChar::Predicate(Predicate::new(
+        "matching", predicate,
+    )))
+}
+
+/// A decimal digit (general category `Nd`) from any script, like `\d`.
+/// The digits come from a table of Unicode 14.0, so digits added to later
+/// versions don't match.
+pub fn digit() -> NFA {
+    static DIGIT: OnceLock<Predicate> = OnceLock::new();
+    shared_class(&DIGIT, "digit", is_decimal_digit)
+}
+
+/// An alphanumeric character (`char::is_alphanumeric`) or `_`, like `\w`.
+/// Unlike some regex engines, combining marks aren't included.
+pub fn word_char() -> NFA {
+    static WORD_CHAR: OnceLock<Predicate> = OnceLock::new();
+    shared_class(&WORD_CHAR, "word", |ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// A character with the Unicode `White_Space` property, like `\s`.
+pub fn whitespace() -> NFA {
+    static WHITESPACE: OnceLock<Predicate> = OnceLock::new();
+    shared_class(&WHITESPACE, "space", char::is_whitespace)
+}
+
+/// `0` to `9` only.
+pub fn ascii_digit() -> NFA {
+    static ASCII_DIGIT: OnceLock<Predicate> = OnceLock::new();
+    shared_class(&ASCII_DIGIT, "ascii_digit", |ch| ch.is_ascii_digit())
+}
+
+/// An ASCII letter or digit, or `_`.
+pub fn ascii_word_char() -> NFA {
+    static ASCII_WORD_CHAR: OnceLock<Predicate> = OnceLock::new();
+    shared_class(&ASCII_WORD_CHAR, "ascii_word", |ch| {
+        ch.is_ascii_alphanumeric() || ch == '_'
+    })
+}
+
+/// ASCII whitespace as defined by `char::is_ascii_whitespace`.
+pub fn ascii_whitespace() -> NFA {
+    static ASCII_WHITESPACE: OnceLock<Predicate> = OnceLock::new();
+    shared_class(&ASCII_WHITESPACE, "ascii_space", |ch| {
+        ch.is_ascii_whitespace()
+    })
+}
+
+/// A single `Predicate` edge whose predicate is created once per `cell`, so
+/// every call yields the same label and the results compare equal.
+fn shared_class(cell: &'static OnceLock<Predicate>, name: &str, test: fn(char) -> bool) -> NFA {
+    unit(ExtendedChar::Predicate(
+        cell.get_or_init(|| Predicate::new(name, test)).clone(),
+    ))
+}
+
+/// The first code point of every run of ten decimal digits in Unicode 14.0.
+const DECIMAL_DIGIT_ZEROS: [u32; 66] = [
+    0x0030, 0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6, 0x0C66, 0x0CE6,
+    0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80,
+    0x1A90, 0x1B50, 0x1BB0, 0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0,
+    0xFF10, 0x104A0, 0x10D30, 0x11066, 0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0,
+    0x11650, 0x116C0, 0x11730, 0x118E0, 0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x16A60, 0x16AC0,
+    0x16B50, 0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E140, 0x1E2F0, 0x1E950, 0x1FBF0,
+];
+
+fn is_decimal_digit(ch: char) -> bool {
+    let code = ch as u32;
+    match DECIMAL_DIGIT_ZEROS.binary_search(&code) {
+        Ok(_) => true,
+        Err(0) => false,
+        Err(i) => code - DECIMAL_DIGIT_ZEROS[i - 1] < 10,
+    }
+}
+
+/// `ch` in either case. Only single-character case mappings are used, so
+/// characters whose other case expands to several characters (such as `ß`,
+/// which uppercases to `SS`) match just themselves and their single-character
+/// forms.
+pub fn unit_ci(ch: char) -> NFA {
+    single_of(case_variants(ch))
+}
+
+/// Like `class`, but also matches the other case of every character in
+/// `ranges`, with the same limitation as `unit_ci`.
+pub fn class_ci(ranges: &[(char, char)]) -> NFA {
+    single_of(
+        ranges
+            .iter()
+            .flat_map(|&(low, high)| low..=high)
+            .flat_map(case_variants),
+    )
+}
+
+pub(crate) fn case_variants(ch: char) -> impl Iterator<Item = char> {
+    fn single(mut mapped: impl Iterator<Item = char>) -> Option<char> {
+        match (mapped.next(), mapped.next()) {
+            (Some(mapped), None) => Some(mapped),
+            _ => None,
+        }
+    }
+    std::iter::once(ch)
+        .chain(single(ch.to_lowercase()))
+        .chain(single(ch.to_uppercase()))
+}
+
+fn single_of(chars: impl Iterator<Item = char>) -> NFA {
+    NFA {
+        states: 2,
+        starting: [Node(0)].into(),
+        delta: chars
+            .map(|ch| ((Node(0), ExtendedChar::Char(ch)), [Node(1)].into()))
+            .collect(),
+        epsilon: [].into(),
+        finished: [Node(1)].into(),
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// One or more repetitions of `nfa`. Every finished node gets an epsilon edge
+/// back to the starting nodes, which are left as they are, so the first
+/// repetition stays mandatory.
+///
+/// A finished node that is also starting (the inner language contains `""`)
+/// would get an epsilon self-loop, which consumes nothing and is skipped.
+pub fn plus_rep(nfa: &NFA) -> NFA {
+    let mut epsilon = nfa.epsilon.clone();
+    for &node in nfa.finished.iter() {
+        let targets = nfa.starting.iter().copied().filter(|&start| start != node);
+        epsilon.entry(node).or_default().extend(targets);
+    }
+    epsilon.retain(|_, set| !set.is_empty());
+
+    NFA {
+        states: nfa.states,
+        starting: nfa.starting.clone(),
+        delta: nfa.delta.clone(),
+        epsilon,
+        finished: nfa.finished.clone(),
+        labels: nfa.labels.clone(),
+        branches: [].into(),
+    }
+}
+
+/// Zero or more repetitions of `nfa`, the same as `star_fresh`.
+pub fn star(nfa: &NFA) -> NFA {
+    star_fresh(nfa)
+}
+
+/// The label consuming exactly the characters both `first` and `second`
+/// consume, if there are any. Anchors never meet.
+fn meet(first: &ExtendedChar, second: &ExtendedChar) -> Option<ExtendedChar> {
+    match (first, second) {
+        (ExtendedChar::Wildcard, ec) | (ec, ExtendedChar::Wildcard) if ec.is_consuming() => {
+            Some(ec.clone())
+        }
+        (ExtendedChar::Char(a), ExtendedChar::Char(b)) if a == b => Some(first.clone()),
+        (ExtendedChar::Char(ch), ExtendedChar::NoneOf(_)) if second.matches(*ch) => {
+            Some(first.clone())
+        }
+        (ExtendedChar::NoneOf(_), ExtendedChar::Char(ch)) if first.matches(*ch) => {
+            Some(second.clone())
+        }
+        (ExtendedChar::NoneOf(left), ExtendedChar::NoneOf(right)) => {
+            let mut excluded: Vec<char> = left.iter().chain(right.iter()).copied().collect();
+            excluded.sort();
+            excluded.dedup();
+            Some(ExtendedChar::NoneOf(excluded))
+        }
+        (ExtendedChar::Char(ch), ExtendedChar::Predicate(_)) if second.matches(*ch) => {
+            Some(first.clone())
+        }
+        (ExtendedChar::Predicate(_), ExtendedChar::Char(ch)) if first.matches(*ch) => {
+            Some(second.clone())
+        }
+        (ExtendedChar::Predicate(left), ExtendedChar::Predicate(right)) if left == right => {
+            Some(first.clone())
+        }
+        (ExtendedChar::Predicate(left), ExtendedChar::Predicate(right)) => {
+            Some(ExtendedChar::Predicate(left.and(right)))
+        }
+        (ExtendedChar::Predicate(predicate), none_of @ ExtendedChar::NoneOf(_))
+        | (none_of @ ExtendedChar::NoneOf(_), ExtendedChar::Predicate(predicate)) => {
+            let (predicate, none_of) = (predicate.clone(), none_of.clone());
+            let name = format!("{}&{}", predicate.name(), none_of);
+            Some(ExtendedChar::Predicate(Predicate::new(&name, move |ch| {
+                predicate.test(ch) && none_of.matches(ch)
+            })))
+        }
+        _ => None,
+    }
+}
+
+/// The intersection of the two languages via the product construction. The
+/// pair `(p, q)` is flattened to `p * second.states + q`; a pair is starting
+/// (finished) iff both components are, and it steps on a character iff both
+/// components do. Epsilon edges move one component at a time.
+pub fn intersect(first: &NFA, second: &NFA) -> NFA {
+    let pair = |Node(p): Node, Node(q): Node| Node(p * second.states + q);
+    let product = |left: &HashSet<Node>, right: &HashSet<Node>| -> HashSet<Node> {
+        left.iter()
+            .flat_map(|&p| right.iter().map(move |&q| pair(p, q)))
+            .collect()
+    };
+
+    let mut delta: HashMap<(Node, ExtendedChar), HashSet<Node>> = HashMap::new();
+    for (&(p, ref first_ec), first_set) in first.delta.iter() {
+        for (&(q, ref second_ec), second_set) in second.delta.iter() {
+            let Some(ec) = meet(first_ec, second_ec) else {
+                continue;
+            };
+            delta
+                .entry((pair(p, q), ec))
+                .or_default()
+                .extend(product(first_set, second_set));
+        }
+    }
+
+    let mut epsilon: HashMap<Node, HashSet<Node>> = HashMap::new();
+    for (&p, set) in first.epsilon.iter() {
+        for q in (0..second.states).map(Node) {
+            let targets = set.iter().map(|&next| pair(next, q));
+            epsilon.entry(pair(p, q)).or_default().extend(targets);
+        }
+    }
+    for (&q, set) in second.epsilon.iter() {
+        for p in (0..first.states).map(Node) {
+            let targets = set.iter().map(|&next| pair(p, next));
+            epsilon.entry(pair(p, q)).or_default().extend(targets);
+        }
+    }
+    // like epsilon edges, an anchor edge moves one side while the other stays
+    let is_anchor =
+        |ec: &ExtendedChar| matches!(ec, ExtendedChar::StartOfInput | ExtendedChar::EndOfInput);
+    for (&(p, ref ec), set) in first.delta.iter().filter(|((_, ec), _)| is_anchor(ec)) {
+        for q in (0..second.states).map(Node) {
+            let targets = set.iter().map(|&next| pair(next, q));
+            delta
+                .entry((pair(p, q), ec.clone()))
+                .or_default()
+                .extend(targets);
+        }
+    }
+    for (&(q, ref ec), set) in second.delta.iter().filter(|((_, ec), _)| is_anchor(ec)) {
+        for p in (0..first.states).map(Node) {
+            let targets = set.iter().map(|&next| pair(p, next));
+            delta
+                .entry((pair(p, q), ec.clone()))
+                .or_default()
+                .extend(targets);
+        }
+    }
+
+    NFA {
+        states: first.states * second.states,
+        starting: product(&first.starting, &second.starting),
+        delta,
+        epsilon,
+        finished: product(&first.finished, &second.finished),
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// Every string over `alphabet` that `nfa` rejects. The automaton is
+/// determinized, completed with a dead node over `alphabet` and then has its
+/// accepting and non-accepting nodes swapped. Characters outside `alphabet`
+/// never match. Panics if `nfa` has `Predicate` edges, which `to_dfa` can't
+/// determinize.
+pub fn complement(nfa: &NFA, alphabet: &[char]) -> NFA {
+    let dfa = nfa.to_dfa();
+    let dead = Node(dfa.states);
+    let mut delta: HashMap<(Node, ExtendedChar), HashSet<Node>> = HashMap::new();
+    for state in 0..dfa.states {
+        for &ch in alphabet {
+            let target = dfa.step(state, ch).map(Node).unwrap_or(dead);
+            delta.insert((Node(state), ExtendedChar::Char(ch)), [target].into());
+        }
+    }
+    for &ch in alphabet {
+        delta.insert((dead, ExtendedChar::Char(ch)), [dead].into());
+    }
+
+    NFA {
+        states: dfa.states + 1,
+        starting: [Node(dfa.start)].into(),
+        delta,
+        epsilon: [].into(),
+        finished: (0..=dfa.states)
+            .filter(|state| !dfa.finished.contains(state))
+            .map(Node)
+            .collect(),
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// Strings matched by `first` but not by `second`, i.e.
+/// `intersect(first, complement(second, alphabet))`. As with `complement`,
+/// only strings over `alphabet` can match, and it panics if `second` has
+/// `Predicate` edges.
+pub fn difference(first: &NFA, second: &NFA, alphabet: &[char]) -> NFA {
+    intersect(first, &complement(second, alphabet))
+}
+
+/// Whether every string over `alphabet` accepted by `a` is also accepted by
+/// `b`, checked by making sure `difference(a, b)` can't reach a finished node.
+/// Panics if `b` has `Predicate` edges, like `complement`.
+pub fn is_subset(a: &NFA, b: &NFA, alphabet: &[char]) -> bool {
+    difference(a, b, alphabet).is_empty_language()
+}
+
+/// Whether `a` and `b` recognize the same strings over `alphabet`, i.e. each
+/// `is_subset` of the other. Panics if either has `Predicate` edges.
+pub fn equivalent(a: &NFA, b: &NFA, alphabet: &[char]) -> bool {
+    is_subset(a, b, alphabet) && is_subset(b, a, alphabet)
+}
+
+/// Kleene star through a fresh node that is both the only starting node and
+/// finished, with epsilon edges into `nfa`'s starting nodes and back from its
+/// finished nodes. This never accepts a string that merely re-enters a
+/// starting node through one of its incoming transitions, so only complete
+/// repetitions are accepted.
+pub fn star_fresh(nfa: &NFA) -> NFA {
+    let increase = |&Node(n): &Node| Node(n + 1);
+    let fresh = Node(0);
+    let mut delta = HashMap::new();
+    for ((node, ec), set) in nfa.delta.iter() {
+        delta.insert(
+            (increase(node), ec.clone()),
+            set.iter().map(increase).collect(),
+        );
+    }
+    let mut epsilon: HashMap<Node, HashSet<Node>> = HashMap::new();
+    for (node, set) in nfa.epsilon.iter() {
+        epsilon.insert(increase(node), set.iter().map(increase).collect());
+    }
+    epsilon.insert(fresh, nfa.starting.iter().map(increase).collect());
+    for node in nfa.finished.iter() {
+        epsilon.entry(increase(node)).or_default().insert(fresh);
+    }
+
+    NFA {
+        states: nfa.states + 1,
+        starting: [fresh].into(),
+        delta,
+        epsilon,
+        finished: [fresh].into(),
+        labels: nfa
+            .labels
+            .iter()
+            .map(|(node, &label)| (increase(node), label))
+            .collect(),
+        branches: [].into(),
+    }
+}
+
+/// Zero or one occurrence of `nfa`, by unioning in `empty()`.
+pub fn optional(nfa: &NFA) -> NFA {
+    plus(nfa, &empty())
+}
+
+/// Between `min` and `max` repetitions of `nfa`, with `max = None` meaning
+/// unbounded. Built from `min` mandatory copies followed by either
+/// `max - min` optional copies or a trailing `star`.
+///
+/// # Panics
+///
+/// Panics if `max` is less than `min`.
+pub fn repeat(nfa: &NFA, min: usize, max: Option<usize>) -> NFA {
+    if let Some(max) = max {
+        assert!(min <= max, "repeat: min ({}) exceeds max ({})", min, max);
+    }
+    let mut result = empty();
+    for _ in 0..min {
+        result = times_into(result, nfa);
+    }
+    match max {
+        Some(max) => {
+            let optional_nfa = optional(nfa);
+            for _ in min..max {
+                result = times_into(result, &optional_nfa);
+            }
+        }
+        None => result = times_into(result, &star(nfa)),
+    }
+    result
+}
+
+/// A copy of `nfa` with every finished node labeled `label`, replacing any
+/// earlier label, for `which_match`.
+pub fn labeled(nfa: &NFA, label: u32) -> NFA {
+    let mut result = nfa.clone();
+    result
+        .labels
+        .extend(nfa.finished.iter().map(|&node| (node, label)));
+    result
+}
+
+pub fn empty() -> NFA {
+    NFA {
+        states: 1,
+        starting: [Node(0)].into(),
+        delta: [].into(),
+        epsilon: [].into(),
+        finished: [Node(0)].into(),
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// Concatenation of every NFA in `parts`, folding `times` from the left so
+/// `concat(&[a, b, c])` is `times(&times(&a, &b), &c)`. An empty slice gives
+/// `empty()`. The partial result is extended in place rather than copied at
+/// every step, so prefer this over nesting `times` by hand.
+pub fn concat(parts: &[NFA]) -> NFA {
+    match parts.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.clone(), times_into),
+        None => empty(),
+    }
+}
+
+/// Accepts nothing, not even the empty string: a single node that is
+/// neither finished nor left by any edge. It is the identity for `plus` and
+/// absorbs `times` from either side.
+pub fn nothing() -> NFA {
+    NFA {
+        states: 1,
+        starting: [Node(0)].into(),
+        delta: [].into(),
+        epsilon: [].into(),
+        finished: [].into(),
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// Alternation of every NFA in `parts`, folding `plus` from the left. An
+/// empty slice gives `nothing()`.
+pub fn union(parts: &[NFA]) -> NFA {
+    match parts.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.clone(), plus_into),
+        None => nothing(),
+    }
+}
+
+/// Matches exactly the string `s`, by chaining `unit` over its characters.
+pub fn literal(s: &str) -> NFA {
+    s.chars().collect()
+}
+
+/// Accepts exactly the strings in `words`, as a trie: one node per distinct
+/// prefix, with the whole words finished. Shared prefixes are stored once
+/// and the result is deterministic, so this is much smaller than a `union`
+/// of `literal`s for large dictionaries.
+pub fn from_strings(words: &[&str]) -> NFA {
+    let mut states = 1;
+    let mut delta: HashMap<(Node, ExtendedChar), HashSet<Node>> = HashMap::new();
+    let mut finished = HashSet::new();
+    for word in words {
+        let mut node = Node(0);
+        for ch in word.chars() {
+            let next = delta
+                .entry((node, ExtendedChar::Char(ch)))
+                .or_insert_with(|| {
+                    states += 1;
+                    [Node(states - 1)].into()
+                });
+            node = *next.iter().next().unwrap();
+        }
+        finished.insert(node);
+    }
+    NFA {
+        states,
+        starting: [Node(0)].into(),
+        delta,
+        epsilon: [].into(),
+        finished,
+        labels: [].into(),
+        branches: [].into(),
+    }
+}
+
+/// Any string over `alphabet` that contains a match of `inner`, i.e.
+/// `.*inner.*` with `.` restricted to `alphabet`. Characters outside
+/// `alphabet` only match inside `inner`.
+pub fn contains(inner: &NFA, alphabet: &[char]) -> NFA {
+    let any = all(alphabet);
+    times(&times(&any, inner), &any)
+}
+
+/// A match of `inner` followed by any string over `alphabet`.
+pub fn starts_with(inner: &NFA, alphabet: &[char]) -> NFA {
+    times(inner, &all(alphabet))
+}
+
+/// Any string over `alphabet` followed by a match of `inner`.
+pub fn ends_with(inner: &NFA, alphabet: &[char]) -> NFA {
+    times(&all(alphabet), inner)
+}
+
+/// Every string over `alphabet`, including the empty string. Intersecting
+/// with this restricts another automaton to `alphabet` and changes nothing
+/// else.
+pub fn all(alphabet: &[char]) -> NFA {
+    star_fresh(&single_of(alphabet.iter().copied()))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::nfa::*;
+
+    pub fn test_within_bounds(nfa: &NFA) {
+        for &Node(n) in nfa.starting.iter() {
+            assert!(n < nfa.states);
+        }
+        for &Node(n) in nfa.finished.iter() {
+            assert!(n < nfa.states);
+        }
+        for (&(Node(n), _), set) in nfa.delta.iter() {
+            assert!(n < nfa.states);
+            assert!(set.iter().all(|&Node(m)| m < nfa.states));
+        }
+        for (&Node(n), set) in nfa.epsilon.iter() {
+            assert!(n < nfa.states);
+            assert!(set.iter().all(|&Node(m)| m < nfa.states));
+        }
+        assert!(nfa.branches.keys().all(|node| nfa.starting.contains(node)));
+        for &Node(n) in nfa.labels.keys() {
+            assert!(n < nfa.states);
+        }
+    }
+
+    #[test]
+    pub fn test_random_compositions_within_bounds() {
+        let mut rng = Lcg(0x5eed);
+        for _ in 0..500 {
+            let depth = rng.below(6) as u32;
+            let nfa = random_nfa(&mut rng, depth);
+            test_within_bounds(&nfa);
+            test_within_bounds(&nfa.trim());
+        }
+    }
+
+    #[test]
+    pub fn test_empty() {
+        let nfa = empty();
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match(""));
+    }
+
+    #[test]
+    pub fn test_nonempty_rejects() {
+        let nfa = empty();
+        test_within_bounds(&nfa);
+        assert!(!nfa.is_match("a"));
+    }
+
+    #[test]
+    pub fn test_single_char() {
+        let nfa = unit(ExtendedChar::Char('a'));
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match("a"));
+    }
+
+    #[test]
+    pub fn test_nonsinglechar_rejects() {
+        let nfa = unit(ExtendedChar::Char('a'));
+        test_within_bounds(&nfa);
+        assert!(!nfa.is_match("aa"));
+        assert!(!nfa.is_match(""));
+    }
+
+    #[test]
+    pub fn test_times() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        test_within_bounds(&nfa);
+        println!("NFA ab is {:?}", nfa);
+        assert!(nfa.is_match("ab"));
+        assert!(!nfa.is_match("ba"));
+        let another_nfa = times(&empty(), &nfa);
+        println!("Another nfa is {:?}", another_nfa);
+        assert!(another_nfa.is_match("ab"));
+    }
+
+    #[test]
+    pub fn test_plus() {
+        let nfa = plus(
+            &times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            ),
+            &times(
+                &unit(ExtendedChar::Char('c')),
+                &unit(ExtendedChar::Char('d')),
+            ),
+        );
+        print!("The nfa is {:?}", nfa);
+        assert!(nfa.is_match("ab"));
+        assert!(nfa.is_match("cd"));
+        assert!(!nfa.is_match("ac"));
+        assert!(!nfa.is_match("cb"));
+    }
+
+    #[test]
+    #[should_panic(expected = "states overflows usize")]
+    pub fn test_plus_overflowing_states() {
+        // far more nodes than could exist; only the count matters here
+        let huge = NFA {
+            states: usize::MAX - 1,
+            ..empty()
+        };
+        plus(&huge, &unit(ExtendedChar::Char('a')));
+    }
+
+    #[test]
+    #[should_panic(expected = "states overflows usize")]
+    pub fn test_times_overflowing_states() {
+        let huge = NFA {
+            states: usize::MAX,
+            ..empty()
+        };
+        times(&unit(ExtendedChar::Char('a')), &huge);
+    }
+
+    #[test]
+    #[should_panic(expected = "renumbered node overflows usize")]
+    pub fn test_times_inconsistent_states() {
+        // a hand-written automaton claiming fewer states than it uses, which
+        // neither `NFABuilder` nor deserializing would let through
+        let nfa = NFA {
+            states: 1,
+            starting: [Node(usize::MAX)].into(),
+            finished: [Node(usize::MAX)].into(),
+            ..empty()
+        };
+        times(&unit(ExtendedChar::Char('a')), &nfa);
+    }
+
+    #[test]
+    pub fn test_num_transitions_and_density() {
+        let nfa = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        assert_eq!(nfa.num_transitions(), 2);
+        assert_eq!(nfa.density(), 2.0 / 16.0);
+        let looped = star(&dot());
+        assert_eq!(looped.num_transitions(), 1);
+        assert_eq!(union(&[]).num_transitions(), 0);
+        assert_eq!(union(&[]).density(), 0.0);
+    }
+
+    #[test]
+    pub fn test_is_match_bounded() {
+        let nfa = times(&star_fresh(&dot()), &literal("ab"));
+        assert_eq!(nfa.is_match_bounded("xxab", 1000), Ok(true));
+        assert_eq!(nfa.is_match_bounded("xxba", 1000), Ok(false));
+        assert_eq!(
+            nfa.is_match_bounded("xxab", 2),
+            Err(BudgetExceeded { max_steps: 2 })
+        );
+        assert_eq!(nfa.is_match_bounded("", 0), Ok(false));
+        let a = unit(ExtendedChar::Char('a'));
+        assert_eq!(a.is_match_bounded("a", 1), Ok(true));
+        assert!(a.is_match_bounded("a", 0).is_err());
+        // input that kills every node stops counting
+        assert_eq!(a.is_match_bounded(&"b".repeat(1000), 1), Ok(false));
+        assert_eq!(
+            BudgetExceeded { max_steps: 2 }.to_string(),
+            "matching took more than 2 steps"
+        );
+    }
+
+    #[test]
+    pub fn test_operators() {
+        let (a, b, c) = (NFA::from('a'), NFA::from('b'), NFA::from('c'));
+        let alphabet = ['a', 'b', 'c'];
+        assert!(equivalent(&(&a * &b), &times(&a, &b), &alphabet));
+        assert!(equivalent(&(&a + &b), &plus(&a, &b), &alphabet));
+        assert_eq!(&a * &b, times(&a, &b));
+        assert_eq!(&a + &b, plus(&a, &b));
+        // `*` binds tighter than `+`, like concatenation and `|`
+        let nfa = a.clone() * b.clone() + c.clone();
+        test_within_bounds(&nfa);
+        assert!(equivalent(&nfa, &plus(&times(&a, &b), &c), &alphabet));
+        assert!(nfa.is_match("ab"));
+        assert!(nfa.is_match("c"));
+        assert!(!nfa.is_match("ac"));
+        assert!(equivalent(&(nothing() + a.clone()), &a, &alphabet));
+        assert!(equivalent(&(empty() * a.clone()), &a, &alphabet));
+    }
+
+    #[test]
+    pub fn test_from_strings() {
+        let nfa = from_strings(&["cat", "car", "dog"]);
+        test_within_bounds(&nfa);
+        assert!(nfa.is_deterministic());
+        for word in ["cat", "car", "dog"] {
+            assert!(nfa.is_match(word));
+        }
+        for other in ["ca", "cats", "do", "", "c", "dot"] {
+            assert!(!nfa.is_match(other));
+        }
+        // "c", "ca", "cat", "car", "d", "do", "dog" and the root
+        assert_eq!(nfa.num_states(), 8);
+        let alphabet = ['a', 'c', 'd', 'g', 'o', 'r', 't'];
+        let unioned = union(&[literal("cat"), literal("car"), literal("dog")]);
+        assert!(equivalent(&nfa, &unioned, &alphabet));
+        assert!(from_strings(&["", "日本"]).is_match(""));
+        assert!(from_strings(&["", "日本"]).is_match("日本"));
+        assert!(!from_strings(&[]).is_match(""));
+    }
+
+    #[test]
+    pub fn test_nothing() {
+        let nfa = nothing();
+        test_within_bounds(&nfa);
+        assert!(!nfa.is_match(""));
+        assert!(!nfa.is_match("a"));
+        let a = unit(ExtendedChar::Char('a'));
+        let alphabet = ['a', 'b'];
+        assert!(equivalent(&plus(&nothing(), &a), &a, &alphabet));
+        assert!(equivalent(&plus(&a, &nothing()), &a, &alphabet));
+        assert!(equivalent(&times(&a, &nothing()), &nothing(), &alphabet));
+        assert!(equivalent(&times(&nothing(), &a), &nothing(), &alphabet));
+        assert!(equivalent(&union(&[]), &nothing(), &alphabet));
+        assert!(equivalent(
+            &difference(&a, &a, &alphabet),
+            &nothing(),
+            &alphabet
+        ));
+        assert!(star(&nothing()).is_match(""));
+    }
+
+    #[test]
+    pub fn test_all() {
+        let nfa = all(&['a', 'b']);
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match(""));
+        assert!(nfa.is_match("a"));
+        assert!(nfa.is_match("abba"));
+        assert!(!nfa.is_match("abc"));
+        let restricted = intersect(&nfa, &star_fresh(&dot()));
+        for input in ["c", "ac", "abbac", "cab"] {
+            assert!(!restricted.is_match(input));
+        }
+        assert!(restricted.is_match("baab"));
+        assert!(!all(&[]).is_match("a"));
+        assert!(all(&[]).is_match(""));
+    }
+
+    #[test]
+    pub fn test_times_traced() {
+        let a = unit(ExtendedChar::Char('a'));
+        let b = unit(ExtendedChar::Char('b'));
+        let (nfa, provenance) = times_traced(&a, &b);
+        assert_eq!(nfa, times(&a, &b));
+        assert_eq!(provenance.origin(Node(0)), Some((0, Node(0))));
+        assert_eq!(provenance.origin(Node(1)), Some((0, Node(1))));
+        assert_eq!(provenance.origin(Node(2)), Some((1, Node(0))));
+        assert_eq!(provenance.origin(Node(3)), Some((1, Node(1))));
+        assert_eq!(provenance.origin(Node(4)), None);
+    }
+
+    #[test]
+    pub fn test_step_set() {
+        // 0 -a-> 1 -ε-> 2, 0 -.-> 3, 0 -[^a]-> 4
+        let nfa = NFA {
+            states: 5,
+            starting: [Node(0)].into(),
+            delta: [
+                ((Node(0), ExtendedChar::Char('a')), [Node(1)].into()),
+                ((Node(0), ExtendedChar::Wildcard), [Node(3)].into()),
+                ((Node(0), ExtendedChar::NoneOf(vec!['a'])), [Node(4)].into()),
+            ]
+            .into(),
+            epsilon: [(Node(1), [Node(2)].into())].into(),
+            finished: [Node(2)].into(),
+            labels: [].into(),
+            branches: [].into(),
+        };
+        let scanned = nfa.scanned_edges();
+        let start: HashSet<Node> = [Node(0)].into();
+        assert_eq!(
+            nfa.step_set(&start, 'a', &scanned),
+            [Node(1), Node(2), Node(3)].into()
+        );
+        assert_eq!(
+            nfa.step_set(&start, 'b', &scanned),
+            [Node(3), Node(4)].into()
+        );
+        assert_eq!(
+            nfa.step_set(&[Node(1)].into(), 'a', &scanned),
+            HashSet::new()
+        );
+        assert_eq!(nfa.step_set(&HashSet::new(), 'a', &scanned), HashSet::new());
+        // only node 0 has a scanned edge
+        assert_eq!(scanned.targets(Node(0), 'b').collect::<Vec<_>>(), [Node(4)]);
+        assert_eq!(scanned.targets(Node(0), 'a').count(), 0);
+        assert_eq!(scanned.targets(Node(3), 'b').count(), 0);
+    }
+
+    #[test]
+    pub fn test_is_match_reader() {
+        use std::io::{Cursor, Read};
+        let nfa = times(&literal("日本"), &star_fresh(&literal("ab")));
+        for input in ["", "日本", "日本abab", "日本aba", "日", "x"] {
+            assert_eq!(
+                nfa.is_match_reader(Cursor::new(input)).unwrap(),
+                nfa.is_match(input),
+                "{:?}",
+                input
+            );
+        }
+        assert!(empty().is_match_reader(Cursor::new("")).unwrap());
+        // one byte per read, so every multi-byte character is split
+        let bytes = "日本ab".as_bytes();
+        struct OneByte<'a>(&'a [u8]);
+        impl Read for OneByte<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let Some((&first, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                buf[0] = first;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+        assert!(nfa.is_match_reader(OneByte(bytes)).unwrap());
+        assert!(!nfa.is_match_reader(OneByte("日本a".as_bytes())).unwrap());
+        let long = "ab".repeat(10_000);
+        let nfa = star_fresh(&literal("ab"));
+        assert!(nfa.is_match_reader(Cursor::new(&long)).unwrap());
+        let error = nfa
+            .is_match_reader(Cursor::new(&[b'a', 0xff][..]))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let truncated = &"日".as_bytes()[..2];
+        assert_eq!(
+            literal("日")
+                .is_match_reader(Cursor::new(truncated))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    pub fn test_match_with_trace() {
+        let nfa = literal("abc");
+        assert_eq!(nfa.match_with_trace("abc"), Ok(()));
+        assert_eq!(nfa.match_with_trace("abx"), Err(2));
+        assert_eq!(nfa.match_with_trace("xbc"), Err(0));
+        assert_eq!(nfa.match_with_trace("ab"), Err(2));
+        assert_eq!(nfa.match_with_trace("abcd"), Err(3));
+        assert_eq!(nfa.match_with_trace(""), Err(0));
+        assert_eq!(literal("日本").match_with_trace("日x"), Err(3));
+        assert_eq!(empty().match_with_trace(""), Ok(()));
+        let anchored = times(&times(&start_anchor(), &literal("a")), &end_anchor());
+        assert_eq!(anchored.match_with_trace("a"), Ok(()));
+        assert_eq!(anchored.match_with_trace("aa"), Err(1));
+        for input in ["", "a", "ab", "abab", "abba"] {
+            let nfa = star_fresh(&literal("ab"));
+            assert_eq!(nfa.match_with_trace(input).is_ok(), nfa.is_match(input));
+        }
+    }
+
+    #[test]
+    pub fn test_wildcard() {
+        let nfa = times(&unit(ExtendedChar::Wildcard), &unit(ExtendedChar::Wildcard));
+        assert!(!nfa.is_match("a"));
+        assert!(nfa.is_match("ab"));
+    }
+
+    /// Linear congruential generator, so the automata and inputs below are
+    /// the same on every run.
+    pub(crate) struct Lcg(pub(crate) u64);
+
+    impl Lcg {
+        pub(crate) fn below(&mut self, n: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) % n
+        }
+    }
+
+    /// A random composition of the combinators, at most `depth` deep.
+    fn random_nfa(rng: &mut Lcg, depth: u32) -> NFA {
+        let choice = if depth == 0 {
+            rng.below(5)
+        } else {
+            rng.below(13)
+        };
+        let sub = |rng: &mut Lcg| random_nfa(rng, depth - 1);
+        match choice {
+            0 => unit(ExtendedChar::Char('a')),
+            1 => unit(ExtendedChar::Char('b')),
+            2 => unit(ExtendedChar::Wildcard),
+            3 => empty(),
+            4 => nothing(),
+            5 | 6 => plus(&sub(rng), &sub(rng)),
+            7 | 8 => times(&sub(rng), &sub(rng)),
+            9 => star(&sub(rng)),
+            10 => star_fresh(&sub(rng)),
+            11 => plus_rep(&sub(rng)),
+            _ => labeled(&sub(rng), rng.below(3) as u32),
+        }
+    }
+
+    /// Whether some path of `nfa` consumes all of `chars`, found by a
+    /// depth-first search over (node, position) configurations, each visited
+    /// once. This is the search the threaded matcher was meant to run.
+    fn search_match(nfa: &NFA, chars: &[char]) -> bool {
+        let mut seen: HashSet<(Node, usize)> = HashSet::new();
+        let mut pending: Vec<(Node, usize)> = nfa.starting.iter().map(|&node| (node, 0)).collect();
+        while let Some((node, i)) = pending.pop() {
+            if !seen.insert((node, i)) {
+                continue;
+            }
+            for &next in nfa.epsilon.get(&node).into_iter().flatten() {
+                pending.push((next, i));
+            }
+            if i == chars.len() {
+                if nfa.finished.contains(&node) {
+                    return true;
+                }
+                continue;
+            }
+            for ec in [ExtendedChar::Char(chars[i]), ExtendedChar::Wildcard] {
+                for &next in nfa.delta.get(&(node, ec)).into_iter().flatten() {
+                    pending.push((next, i + 1));
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    pub fn test_is_match_agrees_with_search() {
+        let mut rng = Lcg(0x5eed);
+        for _ in 0..100 {
+            let depth = rng.below(5) as u32;
+            let nfa = random_nfa(&mut rng, depth);
+            for _ in 0..30 {
+                let len = rng.below(8);
+                let chars: Vec<char> = (0..len)
+                    .map(|_| ['a', 'b', 'c'][rng.below(3) as usize])
+                    .collect();
+                let input: String = chars.iter().collect();
+                assert_eq!(
+                    nfa.is_match(&input),
+                    search_match(&nfa, &chars),
+                    "{:?} on {:?}",
+                    nfa,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_star_simple() {
+        let nfa = star(&unit(ExtendedChar::Char('a')));
+        test_within_bounds(&nfa);
+        println!("NFA a* is {:?}", nfa);
+        assert!(nfa.is_match(""));
+        assert!(nfa.is_match("a"));
+        assert!(nfa.is_match("aa"));
+        assert!(!nfa.is_match("aba"));
+        let another_nfa = times(
+            &star(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &star(&unit(ExtendedChar::Char('c'))),
+        );
+        println!("NFA2 is {:?}", another_nfa);
+        assert!(another_nfa.is_match("ababab"));
+        assert!(another_nfa.is_match("ababccc"));
+        assert!(!another_nfa.is_match("abb"));
+    }
+
+    #[test]
+    pub fn test_star_with_plus_and_times() {
+        let nfa = times(
+            &star(&plus(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &star(&unit(ExtendedChar::Char('c'))),
+        );
+        assert!(nfa.is_match("a"));
+        assert!(nfa.is_match("abababbbaba"));
+        assert!(nfa.is_match("abababbbabaccc"));
+        assert!(!nfa.is_match("ababaaaababbaccbc"));
+        test_within_bounds(&nfa);
+        let another_nfa = star(&times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        ));
+        test_within_bounds(&another_nfa);
+        assert!(!another_nfa.is_match("abb"));
+    }
+
+    #[test]
+    pub fn test_plus_rep_simple() {
+        let nfa = plus_rep(&unit(ExtendedChar::Char('a')));
+        test_within_bounds(&nfa);
+        assert!(!nfa.is_match(""));
+        assert!(nfa.is_match("a"));
+        assert!(nfa.is_match("aa"));
+        assert!(!nfa.is_match("aba"));
+        let another_nfa = times(
+            &plus_rep(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &star(&unit(ExtendedChar::Char('c'))),
+        );
+        assert!(another_nfa.is_match("ababab"));
+        assert!(another_nfa.is_match("ababccc"));
+        assert!(!another_nfa.is_match("ccc"));
+        assert!(!another_nfa.is_match("abb"));
+    }
+
+    #[test]
+    pub fn test_plus_rep_keeps_empty() {
+        let nfa = plus_rep(&star(&unit(ExtendedChar::Char('a'))));
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match(""));
+        assert!(nfa.is_match("aaa"));
+        assert_eq!(nfa.states, 3);
+    }
+
+    /// The strings over `alphabet` of at most `len` characters.
+    fn strings_up_to(alphabet: &[char], len: usize) -> Vec<String> {
+        let mut strings = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..len {
+            last = last
+                .iter()
+                .flat_map(|s| alphabet.iter().map(move |ch| format!("{}{}", s, ch)))
+                .collect();
+            strings.extend(last.iter().cloned());
+        }
+        strings
+    }
+
+    #[test]
+    pub fn test_plus_rep_is_times_star() {
+        let a = unit(ExtendedChar::Char('a'));
+        let b = unit(ExtendedChar::Char('b'));
+        let inners = [
+            times(&star(&a), &b),
+            plus(&times(&a, &b), &star(&b)),
+            times(&star(&a), &plus_rep(&times(&b, &a))),
+            times(&a, &star(&unit(ExtendedChar::Wildcard))),
+        ];
+        for inner in inners.iter() {
+            let nfa = plus_rep(inner);
+            let reference = times(inner, &star(inner));
+            test_within_bounds(&nfa);
+            for input in strings_up_to(&['a', 'b', 'c'], 5) {
+                assert_eq!(
+                    nfa.is_match(&input),
+                    reference.is_match(&input),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_star_rejects_partial_repetitions() {
+        // the start of `b` has an incoming edge from star(a)'s loop
+        let inner = times(
+            &star(&unit(ExtendedChar::Char('a'))),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let nfa = star(&inner);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("", true),
+            ("b", true),
+            ("ab", true),
+            ("aabb", true),
+            ("a", false),
+            ("aa", false),
+            ("aba", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_optional() {
+        let nfa = optional(&unit(ExtendedChar::Char('a')));
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match(""));
+        assert!(nfa.is_match("a"));
+        assert!(!nfa.is_match("aa"));
+        assert!(!nfa.is_match("b"));
+    }
+
+    #[test]
+    pub fn test_optional_start_with_incoming_edges() {
+        let nfa = optional(&times(
+            &star(&unit(ExtendedChar::Char('a'))),
+            &unit(ExtendedChar::Char('b')),
+        ));
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match(""));
+        assert!(nfa.is_match("aab"));
+        assert!(!nfa.is_match("a"));
+        assert!(!nfa.is_match("bb"));
+    }
+
+    #[test]
+    pub fn test_repeat_bounded() {
+        let nfa = repeat(&unit(ExtendedChar::Char('a')), 0, Some(2));
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("", true),
+            ("a", true),
+            ("aa", true),
+            ("aaa", false),
+            ("b", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "a{{0,2}} on {:?}", input);
+        }
+        let nfa = repeat(&unit(ExtendedChar::Char('a')), 2, Some(2));
+        test_within_bounds(&nfa);
+        for (input, expected) in [("", false), ("a", false), ("aa", true), ("aaa", false)] {
+            assert_eq!(nfa.is_match(input), expected, "a{{2,2}} on {:?}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_repeat_unbounded() {
+        let nfa = repeat(&unit(ExtendedChar::Char('a')), 2, None);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("", false),
+            ("a", false),
+            ("aa", true),
+            ("aaa", true),
+            ("aaaaaa", true),
+            ("aab", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "a{{2,}} on {:?}", input);
+        }
+        let nfa = repeat(&unit(ExtendedChar::Char('a')), 0, None);
+        for (input, expected) in [("", true), ("a", true), ("aaa", true), ("b", false)] {
+            assert_eq!(nfa.is_match(input), expected, "a{{0,}} on {:?}", input);
+        }
+        // the start of `b` has an incoming edge, which a star must not accept at
+        let inner = times(
+            &star(&unit(ExtendedChar::Char('a'))),
+            &unit(ExtendedChar::Char('b')),
+        );
+        for min in [0, 1] {
+            let nfa = repeat(&inner, min, None);
+            test_within_bounds(&nfa);
+            for (input, expected) in [
+                ("", min == 0),
+                ("a", false),
+                ("aa", false),
+                ("ab", true),
+                ("abaab", true),
+                ("aba", false),
+            ] {
+                assert_eq!(
+                    nfa.is_match(input),
+                    expected,
+                    "(a*b){{{},}} on {:?}",
+                    min,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_repeat_min_exceeds_max() {
+        repeat(&unit(ExtendedChar::Char('a')), 3, Some(2));
+    }
+
+    #[test]
+    pub fn test_find() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        assert_eq!(nfa.find("xaby"), Some((1, 3)));
+        assert_eq!(nfa.find("ab"), Some((0, 2)));
+        assert_eq!(nfa.find("xay"), None);
+        assert_eq!(nfa.find(""), None);
+    }
+
+    #[test]
+    pub fn test_matches_at() {
+        let nfa = literal("bc");
+        assert_eq!(nfa.matches_at("abc", 1), Some(3));
+        assert_eq!(nfa.matches_at("abc", 0), None);
+        assert_eq!(nfa.matches_at("abc", 3), None);
+        let nfa = star_fresh(&literal("ab"));
+        assert_eq!(nfa.matches_at("xabab", 1), Some(5));
+        assert_eq!(nfa.matches_at("xabab", 0), Some(0));
+        assert_eq!(nfa.matches_at("xabab", 5), Some(5));
+        let anchored = times(&start_anchor(), &literal("a"));
+        assert_eq!(anchored.matches_at("aa", 0), Some(1));
+        assert_eq!(anchored.matches_at("aa", 1), None);
+        assert_eq!(literal("本").matches_at("日本", 3), Some(6));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a char boundary")]
+    pub fn test_matches_at_inside_char() {
+        literal("本").matches_at("日本", 1);
+    }
+
+    #[test]
+    pub fn test_find_with_policy() {
+        let nfa = star(&unit(ExtendedChar::Char('a')));
+        assert_eq!(nfa.find_with("aaa", MatchPolicy::Greedy), Some((0, 3)));
+        assert_eq!(nfa.find_with("aaa", MatchPolicy::Lazy), Some((0, 0)));
+        let nfa = times(&literal("a"), &star_fresh(&dot()));
+        assert_eq!(nfa.find_with("xabc", MatchPolicy::Greedy), Some((1, 4)));
+        assert_eq!(nfa.find_with("xabc", MatchPolicy::Lazy), Some((1, 2)));
+        let nfa = times(&literal("ab"), &end_anchor());
+        assert_eq!(nfa.find_with("abab", MatchPolicy::Lazy), Some((2, 4)));
+        assert_eq!(nfa.find_with("xyz", MatchPolicy::Lazy), None);
+        assert_eq!(
+            nfa.find_with("abab", MatchPolicy::default()),
+            nfa.find("abab")
+        );
+    }
+
+    #[test]
+    pub fn test_find_leftmost_longest() {
+        let nfa = star(&unit(ExtendedChar::Char('a')));
+        assert_eq!(nfa.find("baa"), Some((0, 0)));
+        assert_eq!(nfa.find("aab"), Some((0, 2)));
+        assert_eq!(nfa.find(""), Some((0, 0)));
+        let nfa = plus(
+            &unit(ExtendedChar::Char('a')),
+            &times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            ),
+        );
+        assert_eq!(nfa.find("xabab"), Some((1, 3)));
+    }
+
+    #[test]
+    pub fn test_find_multibyte_offsets() {
+        let nfa = unit(ExtendedChar::Char('b'));
+        assert_eq!(nfa.find("éb"), Some((2, 3)));
+    }
+
+    #[test]
+    pub fn test_find_all() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        assert_eq!(nfa.find_all("ababxab"), vec![(0, 2), (2, 4), (5, 7)]);
+        assert_eq!(nfa.find_all("xyz"), vec![]);
+    }
+
+    #[test]
+    pub fn test_find_all_zero_width() {
+        let nfa = star(&unit(ExtendedChar::Char('a')));
+        assert_eq!(nfa.find_all("bbb"), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(nfa.find_all("baa"), vec![(0, 0), (1, 3), (3, 3)]);
+        assert_eq!(nfa.find_all(""), vec![(0, 0)]);
+    }
+
+    #[test]
+    pub fn test_epsilon_closure() {
+        let nfa = NFA {
+            states: 4,
+            starting: [Node(0)].into(),
+            delta: [((Node(2), ExtendedChar::Char('a')), [Node(3)].into())].into(),
+            epsilon: [
+                (Node(0), [Node(1)].into()),
+                (Node(1), [Node(2), Node(0)].into()),
+            ]
+            .into(),
+            finished: [Node(3)].into(),
+            labels: [].into(),
+            branches: [].into(),
+        };
+        let closure = nfa.epsilon_closure(&[Node(0)].into());
+        assert_eq!(closure, [Node(0), Node(1), Node(2)].into());
+        assert_eq!(nfa.epsilon_closure(&[Node(3)].into()), [Node(3)].into());
+        assert!(nfa.is_match("a"));
+        assert!(!nfa.is_match(""));
+    }
+
+    #[test]
+    pub fn test_times_links_with_epsilon() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        test_within_bounds(&nfa);
+        assert_eq!(
+            nfa.epsilon_closure(&[Node(1)].into()),
+            [Node(1), Node(2)].into()
+        );
+        let nfa = star(&times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        ));
+        // the finished node links back to the fresh start, which leads on
+        assert_eq!(
+            nfa.epsilon_closure(&[Node(4)].into()),
+            [Node(4), Node(0), Node(1)].into()
+        );
+    }
+
+    #[test]
+    pub fn test_class() {
+        let nfa = class(&[('a', 'c')]);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("a", true),
+            ("b", true),
+            ("c", true),
+            ("d", false),
+            ("", false),
+            ("ab", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "[a-c] on {:?}", input);
+        }
+        let nfa = class(&[('a', 'z'), ('0', '9')]);
+        for (input, expected) in [("q", true), ("7", true), ("A", false), ("-", false)] {
+            assert_eq!(nfa.is_match(input), expected, "[a-z0-9] on {:?}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_negated_class() {
+        let nfa = negated_class(&[('a', 'b')], &['a', 'b', 'c', 'd']);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("a", false),
+            ("b", false),
+            ("c", true),
+            ("d", true),
+            ("e", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "[^ab] on {:?}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_find_overlapping() {
+        let aa = literal("aa");
+        assert_eq!(aa.find_overlapping("aaa"), vec![(0, 2), (1, 3)]);
+        assert_eq!(aa.find_all("aaa"), vec![(0, 2)]);
+        let a_plus = times(
+            &unit(ExtendedChar::Char('a')),
+            &star_fresh(&unit(ExtendedChar::Char('a'))),
+        );
+        assert_eq!(a_plus.find_overlapping("aab"), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(
+            star_fresh(&literal("日")).find_overlapping("日"),
+            vec![(0, 0), (0, 3), (3, 3)]
+        );
+        assert_eq!(
+            times(&literal("a"), &end_anchor()).find_overlapping("aa"),
+            vec![(1, 2)]
+        );
+        assert!(aa.find_overlapping("").is_empty());
+    }
+
+    #[test]
+    pub fn test_none_of() {
+        let nfa = none_of(&['a']);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("b", true),
+            ("z", true),
+            ("a", false),
+            ("", false),
+            ("bb", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "[^a] on {:?}", input);
+        }
+        // exact edges are still followed next to the `NoneOf` edge
+        let nfa = times(
+            &plus(&none_of(&['c', 'a', 'c']), &unit(ExtendedChar::Char('a'))),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let dfa = nfa.to_dfa();
+        let canonical = nfa.canonicalize();
+        let compiled = nfa.compile();
+        for input in ["ab", "bb", "日b", "cb", "b", "abb"] {
+            let expected = input.chars().count() == 2 && !input.starts_with('c');
+            assert_eq!(nfa.is_match(input), expected, "{:?}", input);
+            assert_eq!(dfa.is_match(input), expected, "{:?}", input);
+            assert_eq!(
+                dfa.minimize().to_table().is_match(input),
+                expected,
+                "{:?}",
+                input
+            );
+            assert_eq!(canonical.is_match(input), expected, "{:?}", input);
+            assert_eq!(compiled.is_match(input), expected, "{:?}", input);
+        }
+        let both = intersect(&none_of(&['a']), &none_of(&['b']));
+        assert!(both.is_match("c"));
+        assert!(!both.is_match("a") && !both.is_match("b"));
+        assert_eq!(
+            none_of(&['a', 'b']).shortest_match(),
+            Some(String::from("c"))
+        );
+        assert_eq!(none_of(&['a']).alphabet(), ['a'].into());
+    }
+
+    #[test]
+    pub fn test_which_match() {
+        let scanner = union(&[
+            labeled(&literal("if"), 0),
+            labeled(&literal("in"), 1),
+            labeled(&star_fresh(&class(&[('a', 'z')])), 2),
+        ]);
+        assert_eq!(scanner.which_match("if"), Some(0));
+        assert_eq!(scanner.which_match("in"), Some(1));
+        assert_eq!(scanner.which_match("ix"), Some(2));
+        assert_eq!(scanner.which_match(""), Some(2));
+        assert_eq!(scanner.which_match("i1"), None);
+        assert!(scanner.to_string().contains("\nlabels: {4: 0, 9: 1, "));
+        // labels survive composition as long as their nodes stay finished, and
+        // unlabeled finals don't count
+        let anchored = times(&start_anchor(), &scanner);
+        assert_eq!(times(&scanner, &end_anchor()).which_match("in"), None);
+        assert_eq!(anchored.which_match("in"), Some(1));
+        assert_eq!(literal("if").which_match("if"), None);
+        assert_eq!(
+            plus(&literal("x"), &labeled(&literal("x"), 7)).which_match("x"),
+            Some(7)
+        );
+    }
+
+    #[test]
+    pub fn test_derivative() {
+        let ab = literal("ab");
+        let derivative = ab.derivative('a');
+        assert!(derivative.is_match("b"));
+        assert!(!derivative.is_match("ab"));
+        assert!(!derivative.is_match(""));
+        assert!(ab.derivative('b').is_empty_language());
+        assert!(derivative.derivative('b').accepts_empty());
+        let ab_star_c = times(
+            &star_fresh(&plus(&literal("ab"), &dot())),
+            &unit(ExtendedChar::Char('c')),
+        );
+        let anchored = times(&start_anchor(), &literal("a"));
+        for input in ["", "c", "abc", "xc", "abab", "ca", "a", "aa"] {
+            for nfa in [&ab_star_c, &anchored] {
+                let matched = input
+                    .chars()
+                    .fold(nfa.clone(), |nfa, ch| nfa.derivative(ch))
+                    .accepts_empty();
+                assert_eq!(matched, nfa.is_match(input), "{:?}", input);
+            }
+        }
+        // "a" then "^a" can't match, so the start anchor must not hold later
+        assert!(!times(&literal("a"), &anchored)
+            .derivative('a')
+            .is_match("a"));
+    }
+
+    #[test]
+    pub fn test_complete() {
+        let a = unit(ExtendedChar::Char('a'));
+        let completed = a.complete(&['a', 'b']);
+        test_within_bounds(&completed);
+        let dead = Node(2);
+        assert_eq!(completed.num_states(), 3);
+        assert!(!completed.is_final(dead));
+        for ch in ['a', 'b'] {
+            assert_eq!(
+                completed.delta[&(dead, ExtendedChar::Char(ch))],
+                [dead].into()
+            );
+            assert_eq!(
+                completed.delta[&(Node(1), ExtendedChar::Char(ch))],
+                [dead].into()
+            );
+        }
+        assert_eq!(
+            completed.delta[&(Node(0), ExtendedChar::Char('b'))],
+            [dead].into()
+        );
+        assert_eq!(
+            completed.delta[&(Node(0), ExtendedChar::Char('a'))],
+            [Node(1)].into()
+        );
+        assert_eq!(completed.delta.len(), 6);
+        for input in ["", "a", "b", "ab", "aa"] {
+            assert_eq!(completed.is_match(input), a.is_match(input), "{:?}", input);
+        }
+        // a wildcard edge already consumes every character
+        assert_eq!(dot().complete(&['a']).delta.len(), 3);
+    }
+
+    #[test]
+    pub fn test_matching() {
+        let vowel = matching(|ch| "aeiou".contains(ch));
+        test_within_bounds(&vowel);
+        for (input, expected) in [
+            ("a", true),
+            ("u", true),
+            ("b", false),
+            ("A", false),
+            ("é", false),
+            ("ae", false),
+        ] {
+            assert_eq!(vowel.is_match(input), expected, "vowel on {:?}", input);
+        }
+        let vowels = star_fresh(&vowel);
+        assert_eq!(vowels.find("xxaeoy"), Some((0, 0)));
+        assert_eq!(times(&vowel, &vowels).find("xxaeoy"), Some((2, 5)));
+        // a predicate edge and an exact edge at the same position are alternatives
+        let nfa = plus(&vowel, &unit(ExtendedChar::Char('y')));
+        assert!(nfa.is_match("y") && nfa.is_match("o") && !nfa.is_match("z"));
+        let allowed = ['a', 'b'];
+        let captured = matching(move |ch| allowed.contains(&ch));
+        assert!(captured.is_match("b") && !captured.is_match("c"));
+        assert!(intersect(&vowel, &captured).is_match("a"));
+        assert!(!intersect(&vowel, &captured).is_match("b"));
+        assert!(vowel.to_string().contains("--[:matching:]-->"));
+    }
+
+    #[test]
+    pub fn test_digit() {
+        let nfa = digit();
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("5", true),
+            ("\u{0665}", true),
+            ("a", false),
+            ("½", false),
+            ("55", false),
+            ("", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "\\d on {:?}", input);
+        }
+        assert!(!ascii_digit().is_match("\u{0665}"));
+        assert!(ascii_digit().is_match("7"));
+        assert_eq!(digit(), digit());
+        for ch in ['0', '9', '٠', '٩', '０', '𝟎', '𝟿'] {
+            assert!(digit().is_match(&ch.to_string()), "{:?}", ch);
+        }
+        for ch in ['/', ':', '\u{065F}', '\u{066A}', '²'] {
+            assert!(!digit().is_match(&ch.to_string()), "{:?}", ch);
+        }
+        assert_eq!(digit().shortest_match(), Some(String::from("0")));
+    }
+
+    #[test]
+    pub fn test_word_char_and_whitespace() {
+        let word = times(&word_char(), &star_fresh(&word_char()));
+        assert!(word.is_match("snake_case9"));
+        assert!(word.is_match("日本語"));
+        assert!(!word.is_match("a-b"));
+        assert!(!ascii_word_char().is_match("é"));
+        for input in [" ", "\t", "\n", "\u{3000}"] {
+            assert!(whitespace().is_match(input), "{:?}", input);
+        }
+        assert!(!ascii_whitespace().is_match("\u{3000}"));
+        assert!(!whitespace().is_match("_"));
+        // a word, spaces and a number, found by the NFA and by `Regex`
+        let spaced = times(
+            &times(&word, &star_fresh(&whitespace())),
+            &times(&digit(), &star_fresh(&digit())),
+        );
+        assert_eq!(spaced.find("x: item  42"), Some((3, 11)));
+        assert!(crate::Regex::from(spaced.clone()).is_match("item 42"));
+        assert!(spaced.compile().is_match("item\u{3000}٤٢"));
+        let both = intersect(&word_char(), &none_of(&['_']));
+        assert!(both.is_match("a") && !both.is_match("_") && !both.is_match(" "));
+        let both = intersect(&word_char(), &digit());
+        assert!(both.is_match("3") && !both.is_match("a"));
+        assert_eq!(
+            intersect(&digit(), &unit(ExtendedChar::Char('4'))).shortest_match(),
+            Some(String::from("4"))
+        );
+    }
+
+    #[test]
+    pub fn test_dot() {
+        let nfa = dot();
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("x", true),
+            ("5", true),
+            ("é", true),
+            ("", false),
+            ("xy", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, ". on {:?}", input);
+        }
+        let nfa = plus(
+            &times(&dot(), &unit(ExtendedChar::Char('b'))),
+            &times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('c')),
+            ),
+        );
+        for (input, expected) in [("ab", true), ("ac", true), ("xb", true), ("xc", false)] {
+            assert_eq!(nfa.is_match(input), expected, ".b|ac on {:?}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_to_dot() {
+        let dot = unit(ExtendedChar::Char('a')).to_dot();
+        assert!(dot.starts_with("digraph NFA {"));
+        assert!(dot.contains("0 -> 1 [label=\"a\"]"));
+        assert!(dot.contains("1 [shape=doublecircle]"));
+        assert!(dot.contains("start0 -> 0;"));
+        let dot = times(
+            &unit(ExtendedChar::Char('"')),
+            &unit(ExtendedChar::Wildcard),
+        )
+        .to_dot();
+        assert!(dot.contains("0 -> 1 [label=\"\\\"\"]"));
+        assert!(dot.contains("1 -> 2 [label=\"ε\"]"));
+        assert!(dot.contains("2 -> 3 [label=\".\"]"));
+    }
+
+    #[test]
+    pub fn test_intersect() {
+        let a_or_b = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let ends_in_b = times(&star(&dot()), &unit(ExtendedChar::Char('b')));
+        let nfa = intersect(&star(&a_or_b), &ends_in_b);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("b", true),
+            ("ab", true),
+            ("abab", true),
+            ("", false),
+            ("a", false),
+            ("ba", false),
+            ("cb", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
+        let nfa = intersect(&star(&unit(ExtendedChar::Char('a'))), &ends_in_b);
+        for input in ["", "a", "b", "ab", "aab"] {
+            assert!(!nfa.is_match(input), "on {:?}", input);
         }
-        nodes.iter().any(|node| self.finished.contains(node))
     }
-}
 
-pub fn plus(first: &NFA, second: &NFA) -> NFA {
-    let increase = |&node| {
-        let Node(n) = node;
-        Node(n + first.states)
-    };
-    let states = first.states + second.states;
-    let starting = first
-        .starting
-        .union(&second.starting.iter().map(increase).collect())
-        .copied()
-        .collect();
-    let finished = first
-        .finished
-        .union(&second.finished.iter().map(increase).collect())
-        .copied()
-        .collect();
+    #[test]
+    pub fn test_intersect_wildcards() {
+        let nfa = intersect(
+            &times(&dot(), &dot()),
+            &times(&dot(), &unit(ExtendedChar::Char('x'))),
+        );
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("ax", true),
+            ("xx", true),
+            ("xa", false),
+            ("x", false),
+            ("axx", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
+    }
 
-    let mut delta = first.delta.clone();
+    fn strings_over(alphabet: &[char], max_len: usize) -> Vec<String> {
+        let mut result = vec![String::new()];
+        let mut frontier = vec![String::new()];
+        for _ in 0..max_len {
+            frontier = frontier
+                .iter()
+                .flat_map(|prefix| alphabet.iter().map(move |&ch| format!("{}{}", prefix, ch)))
+                .collect();
+            result.extend(frontier.iter().cloned());
+        }
+        result
+    }
 
-    for (&(Node(n), ch), set) in second.delta.iter() {
-        let set = set.iter().map(increase).collect();
-        delta.insert((Node(n + first.states), ch), set);
+    #[test]
+    pub fn test_complement_of_empty() {
+        let nfa = complement(&empty(), &['a']);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("", false),
+            ("a", true),
+            ("aa", true),
+            ("aaa", true),
+            ("b", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
     }
 
-    NFA {
-        states,
-        starting,
-        delta,
-        finished,
+    #[test]
+    pub fn test_complement_negates() {
+        let alphabet = ['a', 'b', 'c'];
+        let original = times(
+            &star(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &plus(&unit(ExtendedChar::Char('c')), &dot()),
+        );
+        let nfa = complement(&original, &alphabet);
+        test_within_bounds(&nfa);
+        for input in strings_over(&alphabet, 5) {
+            assert_eq!(
+                nfa.is_match(&input),
+                !original.is_match(&input),
+                "on {:?}",
+                input
+            );
+        }
     }
-}
 
-pub fn times(first: &NFA, second: &NFA) -> NFA {
-    let states = first.states + second.states;
-    let increase = |&node: &Node| -> Node {
-        let Node(n) = node;
-        return Node(n + first.states);
-    };
-    let mut starting = first.starting.clone();
-    if first.starting.iter().any(|&node| {
-        first.finished.contains(&node)
-    }) {
-        starting = starting.union(&second.starting.iter().map(increase).collect()).copied().collect();
-    }
-    // any nodes mapping to a first.finished state should map to second.starting states as well
-    let mut delta = first.delta.clone();
-    let finished: HashSet<Node> = second.finished.clone().iter().map(increase).collect();
-    let second_starting: HashSet<Node> = second.starting.clone().iter().map(increase).collect();
-    for (&(Node(n), ch), set) in first.delta.iter() {
-        let mut new_set: HashSet<Node> = set.clone();
-        if set.iter().any(|&node| {
-            first.finished.contains(&node)
-        }) {
-            new_set = new_set.union(&second_starting).copied().collect();
-        }
-        delta.insert((Node(n), ch), new_set);
-    }
-
-    second.delta.iter().for_each(|(&(node, ch), set)| {
-        let new_set = set.iter().map(increase).collect();
-        delta.insert((increase(&node), ch), new_set);
-    });
+    #[test]
+    pub fn test_difference() {
+        let a = || unit(ExtendedChar::Char('a'));
+        let nfa = difference(&star(&a()), &times(&a(), &a()), &['a']);
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("", true),
+            ("a", true),
+            ("aa", false),
+            ("aaa", true),
+            ("aaaa", true),
+            ("b", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
+    }
 
-    NFA {
-        states,
-        starting,
-        delta,
-        finished,
+    #[test]
+    #[should_panic(expected = "predicate edges can't be determinized")]
+    pub fn test_complement_of_predicate() {
+        complement(&digit(), &['a']);
     }
-}
 
-pub fn unit(ec: ExtendedChar) -> NFA {
-    NFA {
-        states: 2,
-        starting: [Node(0)].into(),
-        delta: [((Node(0), ec), [Node(1)].into())].into(),
-        finished: [Node(1)].into(),
+    #[test]
+    pub fn test_equivalent() {
+        let a = || unit(ExtendedChar::Char('a'));
+        let b = || unit(ExtendedChar::Char('b'));
+        let hand_built = NFA {
+            states: 1,
+            starting: [Node(0)].into(),
+            delta: [((Node(0), ExtendedChar::Char('a')), [Node(0)].into())].into(),
+            epsilon: [].into(),
+            finished: [Node(0)].into(),
+            labels: [].into(),
+            branches: [].into(),
+        };
+        assert!(equivalent(&star(&a()), &hand_built, &['a', 'b']));
+        assert!(equivalent(
+            &plus(&a(), &b()),
+            &plus(&b(), &a()),
+            &['a', 'b']
+        ));
+        assert!(equivalent(&star(&star(&a())), &star(&a()), &['a']));
+        assert!(equivalent(
+            &times(&a(), &star(&a())),
+            &plus_rep(&a()),
+            &['a', 'b']
+        ));
+        assert!(equivalent(
+            &repeat(&a(), 0, Some(1)),
+            &optional(&a()),
+            &['a']
+        ));
     }
-}
 
-pub fn star(nfa: &NFA) -> NFA {
-    let mut finished = nfa.finished.clone();
-    let mut delta = nfa.delta.clone();
-    for (&(Node(n), ch), set) in nfa.delta.iter() {
-        let mut new_set = set.clone();
-        if set.iter().any(|&node| {
-            nfa.finished.contains(&node)
-        }) {
-            new_set = new_set.union(&nfa.starting).copied().collect();
+    #[test]
+    pub fn test_not_equivalent() {
+        let a = || unit(ExtendedChar::Char('a'));
+        let b = || unit(ExtendedChar::Char('b'));
+        assert!(!equivalent(&star(&a()), &plus_rep(&a()), &['a']));
+        assert!(!equivalent(
+            &times(&a(), &b()),
+            &times(&b(), &a()),
+            &['a', 'b']
+        ));
+        assert!(!equivalent(&empty(), &star(&a()), &['a']));
+        assert!(!equivalent(&dot(), &a(), &['a', 'b']));
+    }
+
+    #[test]
+    pub fn test_multibyte_chars() {
+        let nfa = unit(ExtendedChar::Char('é'));
+        assert!(nfa.is_match("é"));
+        assert!(!nfa.is_match("e"));
+        let nfa = times(
+            &times(&unit(ExtendedChar::Char('c')), &dot()),
+            &star(&unit(ExtendedChar::Char('日'))),
+        );
+        assert!(nfa.is_match("cé"));
+        assert!(nfa.is_match("c❤日日"));
+        assert!(!nfa.is_match("c"));
+        assert!(!nfa.is_match("cé本"));
+        assert_eq!(nfa.find("xcé日!"), Some((1, 7)));
+        assert!(nfa.to_dfa().is_match("cé日"));
+    }
+
+    #[test]
+    pub fn test_reverse() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        )
+        .reverse();
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match("ba"));
+        assert!(!nfa.is_match("ab"));
+        let nfa = times(
+            &star(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &unit(ExtendedChar::Char('c')),
+        )
+        .reverse();
+        test_within_bounds(&nfa);
+        for (input, expected) in [
+            ("c", true),
+            ("cba", true),
+            ("cbaba", true),
+            ("abc", false),
+            ("cab", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
         }
-        delta.insert((Node(n), ch), new_set);
     }
-    nfa.starting.iter().for_each(|&Node(n)| {
-        finished.insert(Node(n));
-    });
 
-    NFA {
-        states: nfa.states,
-        starting: nfa.starting.clone(),
-        delta,
-        finished,
+    #[test]
+    pub fn test_accessors() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Wildcard),
+        );
+        assert_eq!(nfa.num_states(), 4);
+        assert_eq!(nfa.starting_states().collect::<Vec<_>>(), vec![Node(0)]);
+        let mut transitions: Vec<_> = nfa.transitions().collect();
+        transitions.sort_by_key(|&(Node(from), _, _)| from);
+        assert_eq!(
+            transitions,
+            vec![
+                (Node(0), ExtendedChar::Char('a'), Node(1)),
+                (Node(2), ExtendedChar::Wildcard, Node(3))
+            ]
+        );
+        assert_eq!(
+            nfa.epsilon_transitions().collect::<Vec<_>>(),
+            vec![(Node(1), Node(2))]
+        );
+        assert!(nfa.is_final(Node(3)));
+        assert!(!nfa.is_final(Node(1)));
     }
-}
 
-pub fn empty() -> NFA {
-    NFA {
-        states: 1,
-        starting: [Node(0)].into(),
-        delta: [].into(),
-        finished: [Node(0)].into(),
+    #[test]
+    pub fn test_clone_and_structural_eq() {
+        let nfa = star(&times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        ));
+        let copy = nfa.clone();
+        assert_eq!(copy, nfa);
+        assert!(copy.is_match("abab"));
+        let a = unit(ExtendedChar::Char('a'));
+        assert_eq!(a, unit(ExtendedChar::Char('a')));
+        assert_ne!(a, unit(ExtendedChar::Char('b')));
+        let reordered = plus(&unit(ExtendedChar::Char('b')), &a);
+        assert_ne!(plus(&a, &unit(ExtendedChar::Char('b'))), reordered);
+        assert!(equivalent(
+            &plus(&a, &unit(ExtendedChar::Char('b'))),
+            &reordered,
+            &['a', 'b']
+        ));
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::nfa::*;
+    #[test]
+    pub fn test_long_input_stays_bounded() {
+        let a_or_b = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let nfa = times(
+            &star(&star(&a_or_b)),
+            &times(&unit(ExtendedChar::Char('a')), &repeat(&a_or_b, 3, Some(3))),
+        );
+        let input: String = (0..10_000)
+            .map(|i| if i % 7 < 3 { 'a' } else { 'b' })
+            .collect();
+        assert!(nfa.is_match(&(input.clone() + "abab")));
+        assert!(!nfa.is_match(&(input + "bbbb")));
+    }
 
-    pub fn test_within_bounds(nfa: &NFA) {
-        for &Node(n) in nfa.starting.iter() {
-            assert!(n < nfa.states);
+    #[test]
+    pub fn test_star_of_empty() {
+        let nfa = star(&empty());
+        test_within_bounds(&nfa);
+        assert_eq!(nfa.states, 2);
+        assert!(nfa.is_match(""));
+        assert!(!nfa.is_match("a"));
+        assert!(!nfa.is_match("aa"));
+        let nfa = star(&times(
+            &star(&empty()),
+            &star(&unit(ExtendedChar::Char('a'))),
+        ));
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match(""));
+        assert!(nfa.is_match("aaaa"));
+        assert!(!nfa.is_match("ab"));
+        let nfa = star(&star(&optional(&unit(ExtendedChar::Char('a')))));
+        let input: String = "a".repeat(2_000);
+        assert!(nfa.is_match(&input));
+        assert!(!nfa.is_match(&(input + "b")));
+    }
+
+    #[test]
+    pub fn test_matches_stream() {
+        let nfa = times(
+            &star(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &unit(ExtendedChar::Char('c')),
+        );
+        for input in ["", "c", "abc", "ababc", "abab", "abcc", "é"] {
+            assert_eq!(
+                nfa.matches_stream(input.chars()),
+                nfa.is_match(input),
+                "on {:?}",
+                input
+            );
         }
-        for &Node(n) in nfa.finished.iter() {
-            assert!(n < nfa.states);
+        let chars = std::iter::repeat_n("ab", 1_000)
+            .flat_map(str::chars)
+            .chain(std::iter::once('c'));
+        assert!(nfa.matches_stream(chars));
+        assert!(!nfa.matches_stream(std::iter::repeat_n('a', 3)));
+    }
+
+    #[test]
+    pub fn test_match_state() {
+        let nfa = star(&times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        ));
+        let mut state = nfa.start_state();
+        assert!(state.is_accepting());
+        state.step('a');
+        assert!(!state.is_accepting());
+        state.step('b');
+        assert!(state.is_accepting());
+        let snapshot = state.clone();
+        state.step('b');
+        assert!(!state.is_accepting());
+        state.step('a');
+        assert!(!state.is_accepting());
+        let mut resumed = snapshot;
+        resumed.step('a');
+        resumed.step('b');
+        assert!(resumed.is_accepting());
+    }
+
+    #[test]
+    pub fn test_unit_ci() {
+        let nfa = unit_ci('a');
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match("a"));
+        assert!(nfa.is_match("A"));
+        assert!(!nfa.is_match("b"));
+        assert!(!nfa.is_match("aA"));
+        assert!(unit_ci('É').is_match("é"));
+        let nfa = unit_ci('ß');
+        assert!(nfa.is_match("ß"));
+        assert!(!nfa.is_match("SS"));
+    }
+
+    #[test]
+    pub fn test_class_ci() {
+        let nfa = class_ci(&[('a', 'c')]);
+        for (input, expected) in [
+            ("a", true),
+            ("B", true),
+            ("c", true),
+            ("D", false),
+            ("d", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
         }
     }
 
     #[test]
-    pub fn test_empty() {
-        let nfa = empty();
+    pub fn test_full_vs_partial_match() {
+        let nfa = unit(ExtendedChar::Char('a'));
+        assert!(!nfa.is_full_match("ab"));
+        assert!(nfa.is_partial_match("ab"));
+        assert!(nfa.is_full_match("a"));
+        assert!(nfa.is_partial_match("a"));
+        assert!(!nfa.is_partial_match("ba"));
+        assert!(!nfa.is_partial_match(""));
+        assert!(star(&nfa).is_partial_match("b"));
+    }
+
+    #[test]
+    pub fn test_count_accepting_paths() {
+        let a = || unit(ExtendedChar::Char('a'));
+        assert_eq!(plus(&a(), &a()).count_accepting_paths("a"), 2);
+        assert_eq!(plus(&a(), &a()).count_accepting_paths("b"), 0);
+        assert_eq!(star(&a()).count_accepting_paths("aaa"), 1);
+        assert_eq!(star(&a()).count_accepting_paths(""), 1);
+        assert_eq!(star(&plus(&a(), &a())).count_accepting_paths("aa"), 4);
+        assert_eq!(plus(&a(), &dot()).count_accepting_paths("a"), 2);
+        assert_eq!(plus(&a(), &dot()).count_accepting_paths("b"), 1);
+        assert_eq!(
+            times(&star(&a()), &star(&a())).count_accepting_paths("aa"),
+            3
+        );
+    }
+
+    #[test]
+    pub fn test_count_accepting_paths_saturates() {
+        let nfa = star(&plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('a')),
+        ));
+        assert_eq!(nfa.count_accepting_paths(&"a".repeat(100)), usize::MAX);
+    }
+
+    #[test]
+    pub fn test_enumerate() {
+        assert_eq!(
+            star(&unit(ExtendedChar::Char('a'))).enumerate(3),
+            vec!["", "a", "aa", "aaa"]
+        );
+        let nfa = plus(
+            &times(
+                &unit(ExtendedChar::Char('b')),
+                &unit(ExtendedChar::Char('a')),
+            ),
+            &star(&unit(ExtendedChar::Char('a'))),
+        );
+        assert_eq!(nfa.enumerate(2), vec!["", "a", "aa", "ba"]);
+        let nfa = times(
+            &unit(ExtendedChar::Char('b')),
+            &plus(&unit(ExtendedChar::Char('a')), &dot()),
+        );
+        assert_eq!(nfa.enumerate(5), vec!["ba", "bb"]);
+        assert_eq!(
+            unit(ExtendedChar::Char('a')).enumerate(0),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    pub fn test_is_empty_language() {
+        assert!(!empty().is_empty_language());
+        assert!(!unit(ExtendedChar::Char('a')).is_empty_language());
+        let unreachable_final = NFA {
+            states: 3,
+            starting: [Node(0)].into(),
+            delta: [
+                ((Node(0), ExtendedChar::Char('a')), [Node(1)].into()),
+                ((Node(2), ExtendedChar::Char('a')), [Node(2)].into()),
+            ]
+            .into(),
+            epsilon: [].into(),
+            finished: [Node(2)].into(),
+            labels: [].into(),
+            branches: [].into(),
+        };
+        assert!(unreachable_final.is_empty_language());
+        assert!(intersect(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b'))
+        )
+        .is_empty_language());
+    }
+
+    #[test]
+    pub fn test_is_empty_language_with_anchors() {
+        let a = || literal("a");
+        // nothing can follow an end anchor or precede a start anchor
+        assert!(times(&times(&a(), &end_anchor()), &literal("b")).is_empty_language());
+        assert!(times(&a(), &start_anchor()).is_empty_language());
+        assert!(times(&star(&a()), &start_anchor()).is_match(""));
+        assert!(!times(&star(&a()), &start_anchor()).is_empty_language());
+        assert!(!times(&end_anchor(), &start_anchor()).is_empty_language());
+        assert!(!times(&times(&start_anchor(), &a()), &end_anchor()).is_empty_language());
+        assert!(!times(&a(), &times(&end_anchor(), &end_anchor())).is_empty_language());
+    }
+
+    #[test]
+    pub fn test_is_infinite_language() {
+        let a = || unit(ExtendedChar::Char('a'));
+        assert!(star(&a()).is_infinite_language());
+        assert!(times(&star(&a()), &unit(ExtendedChar::Char('b'))).is_infinite_language());
+        assert!(!times(&a(), &unit(ExtendedChar::Char('b'))).is_infinite_language());
+        assert!(!star(&empty()).is_infinite_language());
+        assert!(!repeat(&a(), 2, Some(5)).is_infinite_language());
+        let dead_loop = NFA {
+            states: 3,
+            starting: [Node(0)].into(),
+            delta: [
+                (
+                    (Node(0), ExtendedChar::Char('a')),
+                    [Node(1), Node(2)].into(),
+                ),
+                ((Node(2), ExtendedChar::Char('a')), [Node(2)].into()),
+            ]
+            .into(),
+            epsilon: [].into(),
+            finished: [Node(1)].into(),
+            labels: [].into(),
+            branches: [].into(),
+        };
+        assert!(!dead_loop.is_infinite_language());
+        let epsilon_loop = NFA {
+            states: 2,
+            starting: [Node(0)].into(),
+            delta: [((Node(0), ExtendedChar::Char('a')), [Node(1)].into())].into(),
+            epsilon: [(Node(0), [Node(0)].into()), (Node(1), [Node(1)].into())].into(),
+            finished: [Node(1)].into(),
+            labels: [].into(),
+            branches: [].into(),
+        };
+        assert!(!epsilon_loop.is_infinite_language());
+    }
+
+    #[test]
+    pub fn test_alphabet() {
+        assert_eq!(
+            plus(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b'))
+            )
+            .alphabet(),
+            ['a', 'b'].into()
+        );
+        assert_eq!(
+            times(&dot(), &star(&unit(ExtendedChar::Char('c')))).alphabet(),
+            ['c'].into()
+        );
+        assert!(empty().alphabet().is_empty());
+    }
+
+    #[test]
+    pub fn test_trim() {
+        let a_or_b = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let bloated = intersect(
+            &star(&a_or_b),
+            &times(&star(&dot()), &unit(ExtendedChar::Char('b'))),
+        );
+        let trimmed = bloated.trim();
+        test_within_bounds(&trimmed);
+        assert!(trimmed.num_states() < bloated.num_states());
+        for input in strings_over(&['a', 'b', 'c'], 4) {
+            assert_eq!(
+                trimmed.is_match(&input),
+                bloated.is_match(&input),
+                "on {:?}",
+                input
+            );
+        }
+        assert_eq!(trimmed.trim(), trimmed);
+    }
+
+    #[test]
+    pub fn test_trim_empty_language() {
+        let nfa = intersect(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        )
+        .trim();
+        assert_eq!(nfa.num_states(), 0);
+        assert!(!nfa.is_match(""));
+        assert!(!nfa.is_match("a"));
+        let nfa = times(&unit(ExtendedChar::Char('a')), &empty()).trim();
+        assert_eq!(nfa.num_states(), 3);
+        assert!(nfa.is_match("a"));
+    }
+
+    #[test]
+    pub fn test_star_fresh() {
+        let ab = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        assert!(!star(&ab).is_match("aba"));
+        let nfa = star_fresh(&ab);
         test_within_bounds(&nfa);
-        let mut stream = CharStream::from_string(String::from(""));
-        assert!(nfa.is_match(&mut stream));
+        for (input, expected) in [
+            ("", true),
+            ("ab", true),
+            ("abab", true),
+            ("a", false),
+            ("aba", false),
+            ("abb", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
     }
 
     #[test]
-    pub fn test_nonempty_rejects() {
-        let nfa = empty();
+    pub fn test_star_fresh_rejects_partial_repetitions() {
+        // the second operand's start is re-entered by `a` (through star(a))
+        let inner = times(
+            &star(&unit(ExtendedChar::Char('a'))),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let nfa = star_fresh(&inner);
         test_within_bounds(&nfa);
-        let mut stream = CharStream::from_string(String::from("a"));
-        assert!(!nfa.is_match(&mut stream));
+        for (input, expected) in [
+            ("", true),
+            ("b", true),
+            ("aab", true),
+            ("abb", true),
+            ("a", false),
+            ("aba", false),
+        ] {
+            assert_eq!(nfa.is_match(input), expected, "on {:?}", input);
+        }
     }
 
     #[test]
-    pub fn test_single_char() {
-        let nfa = unit(ExtendedChar::Char('a'));
+    pub fn test_tokens() {
+        let word = plus_rep(&class(&[('a', 'z')]));
+        assert_eq!(word.tokens("ab12cd").collect::<Vec<_>>(), vec!["ab", "cd"]);
+        assert_eq!(word.tokens("12").count(), 0);
+        assert_eq!(
+            star(&unit(ExtendedChar::Char('a')))
+                .tokens("ba")
+                .collect::<Vec<_>>(),
+            vec!["", "a", ""]
+        );
+    }
+
+    #[test]
+    pub fn test_anchors() {
+        let ab = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let anchored = times(&times(&start_anchor(), &ab), &end_anchor());
+        test_within_bounds(&anchored);
+        assert!(anchored.is_match("ab"));
+        assert!(!anchored.is_match(""));
+        assert_eq!(anchored.find("ab"), Some((0, 2)));
+        assert_eq!(anchored.find("xab"), None);
+        assert_eq!(anchored.find("abab"), None);
+        assert!(!anchored.is_partial_match("abc"));
+
+        let suffix = times(&ab, &end_anchor());
+        assert_eq!(suffix.find_all("abab"), vec![(2, 4)]);
+        assert!(suffix.reverse().is_match("ba"));
+        assert_eq!(times(&start_anchor(), &ab).find_all("abab"), vec![(0, 2)]);
+        assert!(!times(&ab, &start_anchor()).is_match("ab"));
+        assert!(intersect(&anchored, &plus_rep(&dot())).is_match("ab"));
+        assert!(!star(&start_anchor()).is_infinite_language());
+        assert_eq!(anchored.enumerate(3), vec!["ab"]);
+    }
+
+    #[test]
+    pub fn test_literal() {
+        let nfa = literal("abc");
         test_within_bounds(&nfa);
-        let mut stream = CharStream::from_string(String::from("a"));
-        assert!(nfa.is_match(&mut stream));
+        assert!(nfa.is_match("abc"));
+        assert!(!nfa.is_match("ab"));
+        assert!(!nfa.is_match("abcd"));
+        assert!(!nfa.is_match(""));
+        assert_eq!(literal(""), empty());
+        assert!(literal("日本").is_match("日本"));
     }
 
     #[test]
-    pub fn test_nonsinglechar_rejects() {
+    pub fn test_display() {
+        assert_eq!(Node(3).to_string(), "3");
         let nfa = unit(ExtendedChar::Char('a'));
+        let shown = nfa.to_string();
+        assert_eq!(shown, "states: 2\nstart: {0}\nfinal: {1}\n0 --a--> {1}");
+        assert_eq!(format!("{:?}", nfa), shown);
+        let shown = times(&nfa, &plus(&dot(), &nfa)).to_string();
+        assert!(shown.contains("\n1 --ε--> {2, 4}"));
+        assert!(shown.contains("\n2 --.--> {3}"));
+    }
+
+    #[test]
+    pub fn test_formatting_is_deterministic() {
+        // built twice, so the hash maps are seeded and filled independently
+        let build = || {
+            let words: Vec<NFA> = ["alpha", "beta", "gamma", "delta", "epsilon"]
+                .iter()
+                .map(|word| labeled(&literal(word), word.len() as u32))
+                .collect();
+            times(
+                &start_anchor(),
+                &star_fresh(&plus(&union(&words), &class(&[('0', '9')]))),
+            )
+        };
+        let (first, second) = (build(), build());
+        assert_eq!(first, second);
+        assert_eq!(format!("{:?}", first), format!("{:?}", first));
+        assert_eq!(format!("{:?}", first), format!("{:?}", second));
+        assert_eq!(first.to_string(), second.to_string());
+        assert_eq!(first.to_dot(), second.to_dot());
+        let shown = first.to_string();
+        let lines: Vec<&str> = shown.lines().skip(4).collect();
+        let mut sorted = lines.clone();
+        sorted.sort_by_key(|line| line.split(' ').next().unwrap().parse::<usize>().unwrap());
+        assert_eq!(lines, sorted);
+    }
+
+    #[test]
+    pub fn test_accepts_empty() {
+        let a = unit(ExtendedChar::Char('a'));
+        assert!(empty().accepts_empty());
+        assert!(star(&a).accepts_empty());
+        assert!(star_fresh(&a).accepts_empty());
+        assert!(times(&empty(), &optional(&a)).accepts_empty());
+        assert!(times(&start_anchor(), &end_anchor()).accepts_empty());
+        assert!(!a.accepts_empty());
+        assert!(!plus_rep(&a).accepts_empty());
+    }
+
+    #[test]
+    pub fn test_concat() {
+        let (a, b, c) = (
+            unit(ExtendedChar::Char('a')),
+            unit(ExtendedChar::Char('b')),
+            unit(ExtendedChar::Char('c')),
+        );
+        let nfa = concat(&[a.clone(), b.clone(), c.clone()]);
         test_within_bounds(&nfa);
-        let mut stream = CharStream::from_string(String::from("aa"));
-        assert!(!nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from(""));
-        assert!(!nfa.is_match(&mut stream));
+        assert!(nfa.is_match("abc"));
+        assert!(!nfa.is_match("ab"));
+        assert!(!nfa.is_match("cba"));
+        assert_eq!(nfa, times(&times(&a, &b), &c));
+        assert_eq!(concat(&[]), empty());
     }
 
     #[test]
-    pub fn test_times() {
-        let nfa = times(&unit(ExtendedChar::Char('a')), &unit(ExtendedChar::Char('b')));
+    pub fn test_union() {
+        let nfa = union(&[literal("cat"), literal("dog"), literal("bird")]);
         test_within_bounds(&nfa);
-        println!("NFA ab is {:?}", nfa);
-        let mut stream = CharStream::from_string(String::from("ab"));
-        assert!(nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("ba"));
-        assert!(!nfa.is_match(&mut stream));
-        let another_nfa = times(&empty(), &nfa);
-        stream = CharStream::from_string(String::from("ab"));
-        println!("Another nfa is {:?}", another_nfa);
-        assert!(another_nfa.is_match(&mut stream));
+        for word in ["cat", "dog", "bird"] {
+            assert!(nfa.is_match(word));
+        }
+        assert!(!nfa.is_match("fish"));
+        assert!(!nfa.is_match(""));
+        assert!(union(&[]).is_empty_language());
+        assert!(!union(&[]).is_match(""));
     }
 
     #[test]
-    pub fn test_plus() {
-        let nfa = plus(
-            &times(&unit(ExtendedChar::Char('a')), &unit(ExtendedChar::Char('b'))),
-            &times(&unit(ExtendedChar::Char('c')), &unit(ExtendedChar::Char('d'))),
+    pub fn test_is_match_with_max_states_tracked() {
+        // the tenth character from the end is an 'a': every 'a' read keeps
+        // another node alive for the next ten characters
+        let ab = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let tail: Vec<NFA> = std::iter::repeat_n(ab.clone(), 10).collect();
+        let nfa = times(
+            &times(&star_fresh(&ab), &unit(ExtendedChar::Char('a'))),
+            &concat(&tail),
+        );
+        let input = "a".repeat(20);
+
+        assert_eq!(nfa.is_match_with(&input, &MatchConfig::default()), Ok(true));
+        let config = MatchConfig {
+            max_states_tracked: Some(8),
+        };
+        assert_eq!(
+            nfa.is_match_with(&input, &config),
+            Err(MatchError::TooManyStates { limit: 8 })
+        );
+        assert_eq!(nfa.is_match_with("b", &config), Ok(false));
+        assert_eq!(
+            MatchError::TooManyStates { limit: 8 }.to_string(),
+            "more than 8 states active at once"
         );
-        let mut stream1 = CharStream::from_string(String::from("ab"));
-        let mut stream2 = CharStream::from_string(String::from("cd"));
-        let mut stream3 = CharStream::from_string(String::from("ac"));
-        let mut stream4 = CharStream::from_string(String::from("cb"));
-        print!("The nfa is {:?}", nfa);
-        assert!(nfa.is_match(&mut stream1));
-        assert!(nfa.is_match(&mut stream2));
-        assert!(!nfa.is_match(&mut stream3));
-        assert!(!nfa.is_match(&mut stream4));
     }
 
     #[test]
-    pub fn test_wildcard() {
+    pub fn test_match_branch() {
+        let nfa = plus(&literal("a"), &literal("ab"));
+        assert_eq!(nfa.match_branch("a"), Some(0));
+        assert_eq!(nfa.match_branch("ab"), Some(1));
+        assert_eq!(nfa.match_branch("b"), None);
+
+        let nfa = union(&[literal("a"), dot(), literal("ab"), star_fresh(&dot())]);
+        assert_eq!(nfa.match_branch("a"), Some(0));
+        assert_eq!(nfa.match_branch("b"), Some(1));
+        assert_eq!(nfa.match_branch("ab"), Some(2));
+        assert_eq!(nfa.match_branch(""), Some(3));
+
+        // the first alternative has two starting nodes of its own
+        let a = literal("a");
+        let b = literal("b");
+        let nfa = plus(&times(&plus(&a, &b), &literal("c")), &literal("d"));
+        assert_eq!(nfa.match_branch("bc"), Some(0));
+        assert_eq!(nfa.match_branch("d"), Some(1));
+        let nfa = plus(&plus(&a, &b), &plus(&literal("c"), &union(&[])));
+        assert_eq!(nfa.match_branch("b"), Some(1));
+        assert_eq!(nfa.match_branch("c"), Some(2));
+        assert_eq!(times(&nfa, &empty()).match_branch("c"), Some(0));
+        assert_eq!(star(&b).match_branch(""), Some(0));
+    }
+
+    #[test]
+    pub fn test_match_branch_forgets_inner_alternatives() {
+        // (a|ab)c: the concatenation is a single alternative
+        let inner = plus(&literal("a"), &literal("ab"));
+        let nfa = times(&inner, &literal("c"));
+        assert_eq!(nfa.match_branch("ac"), Some(0));
+        assert_eq!(nfa.match_branch("abc"), Some(0));
+        // ac|abc keeps them apart
+        let nfa = plus(&literal("ac"), &literal("abc"));
+        assert_eq!(nfa.match_branch("abc"), Some(1));
+    }
+
+    #[test]
+    pub fn test_contains_starts_with_ends_with() {
+        let alphabet = ['e', 'r', 'o', 'k'];
+        let err = literal("err");
+        let nfa = contains(&err, &alphabet);
+        test_within_bounds(&nfa);
+        assert!(nfa.is_match("okerrok"));
+        assert!(nfa.is_match("err"));
+        assert!(!nfa.is_match("okok"));
+        assert!(!nfa.is_match("okerxok"));
+
+        assert!(starts_with(&err, &alphabet).is_match("errok"));
+        assert!(!starts_with(&err, &alphabet).is_match("okerr"));
+        assert!(ends_with(&err, &alphabet).is_match("okerr"));
+        assert!(!ends_with(&err, &alphabet).is_match("errok"));
+    }
+
+    #[test]
+    pub fn test_longest_accepting_prefix() {
+        let a_star = star(&unit(ExtendedChar::Char('a')));
+        assert_eq!(a_star.longest_accepting_prefix("aaab"), Some(3));
+        assert_eq!(a_star.longest_accepting_prefix("baa"), Some(0));
+        assert_eq!(literal("ab").longest_accepting_prefix("xab"), None);
+        assert_eq!(literal("日").longest_accepting_prefix("日本"), Some(3));
+    }
+
+    #[test]
+    pub fn test_from_char_and_iterator() {
+        let a: NFA = 'a'.into();
+        assert_eq!(a, unit(ExtendedChar::Char('a')));
+        assert_eq!("abc".chars().collect::<NFA>(), literal("abc"));
+        assert_eq!("".chars().collect::<NFA>(), empty());
+        assert!("日本".chars().collect::<NFA>().is_match("日本"));
+    }
+
+    /// Reference for `times`: `s` splits into a match of `first` followed by
+    /// a match of `second`.
+    fn concatenation_matches(first: &NFA, second: &NFA, s: &str) -> bool {
+        s.char_indices()
+            .map(|(i, _)| i)
+            .chain([s.len()])
+            .any(|i| first.is_match(&s[..i]) && second.is_match(&s[i..]))
+    }
+
+    #[test]
+    pub fn test_times_against_reference() {
+        let a = unit(ExtendedChar::Char('a'));
+        let b = unit(ExtendedChar::Char('b'));
+        let nullable = times(&empty(), &empty());
+        test_within_bounds(&nullable);
+        assert!(nullable.is_match(""));
+        assert!(!nullable.is_match("a"));
+
+        let pairs = [
+            (empty(), empty()),
+            (empty(), a.clone()),
+            (a.clone(), empty()),
+            (optional(&a), b.clone()),
+            (optional(&a), optional(&b)),
+            (star(&a), star_fresh(&plus(&a, &b))),
+            (plus_rep(&plus(&a, &b)), times(&b, &optional(&a))),
+        ];
+        for (first, second) in pairs.iter() {
+            let nfa = times(first, second);
+            test_within_bounds(&nfa);
+            for s in strings_over(&['a', 'b'], 4) {
+                assert_eq!(
+                    nfa.is_match(&s),
+                    concatenation_matches(first, second, &s),
+                    "on {:?}",
+                    s
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_is_match_chars() {
         let nfa = times(
-            &unit(ExtendedChar::Wildcard),
-            &unit(ExtendedChar::Wildcard)
+            &literal("日"),
+            &times(&dot(), &unit(ExtendedChar::Char('本'))),
         );
-        let mut stream = CharStream::from_string(String::from("a"));
-        assert!(!nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("ab"));
-        assert!(nfa.is_match(&mut stream));
+        for s in ["日x本", "日本本", "日日本", "日本", "x日本", ""] {
+            let chars: Vec<char> = s.chars().collect();
+            assert_eq!(nfa.is_match_chars(&chars), nfa.is_match(s), "on {:?}", s);
+        }
+        assert!(nfa.is_match_chars(&['日', 'é', '本']));
     }
 
     #[test]
-    pub fn test_star_simple() {
-        let nfa = star(&unit(ExtendedChar::Char('a')));
-        test_within_bounds(&nfa);
-        println!("NFA a* is {:?}", nfa);
-        let mut stream = CharStream::from_string(String::from(""));
-        assert!(nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("a"));
-        assert!(nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("aa"));
-        assert!(nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("aba"));
-        assert!(!nfa.is_match(&mut stream));
-        let another_nfa = times(&star(&times(&unit(ExtendedChar::Char('a')), &unit(ExtendedChar::Char('b')))), &star(&unit(ExtendedChar::Char('c'))));
-        println!("NFA2 is {:?}", another_nfa);
-        stream = CharStream::from_string(String::from("ababab"));
-        assert!(another_nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("ababccc"));
-        assert!(another_nfa.is_match(&mut stream));
-        stream = CharStream::from_string(String::from("abb"));
-        assert!(!another_nfa.is_match(&mut stream));
+    pub fn test_replace_all() {
+        assert_eq!(literal("ab").replace_all("ababc", "X"), "XXc");
+        assert_eq!(literal("ab").replace_all("日ab本", "é"), "日é本");
+        assert_eq!(literal("x").replace_all("abc", "X"), "abc");
+        let a_star = star(&unit(ExtendedChar::Char('a')));
+        assert_eq!(a_star.replace_all("baa", "-"), "-b--");
+        assert_eq!(a_star.replace_all("", "-"), "-");
     }
 
     #[test]
-    pub fn test_star_with_plus_and_times() {
-        let nfa = times(&star(&plus(&unit(ExtendedChar::Char('a')), &unit(ExtendedChar::Char('b')))), &star(&unit(ExtendedChar::Char('c'))));
-        let mut stream1 = CharStream::from_string(String::from("a"));
-        let mut stream2 = CharStream::from_string(String::from("abababbbaba"));
-        let mut stream3 = CharStream::from_string(String::from("abababbbabaccc"));
-        let mut stream4 = CharStream::from_string(String::from("ababaaaababbaccbc"));
-        assert!(nfa.is_match(&mut stream1));
-        assert!(nfa.is_match(&mut stream2));
-        assert!(nfa.is_match(&mut stream3));
-        assert!(!nfa.is_match(&mut stream4));
-        test_within_bounds(&nfa);
-        let another_nfa = star(&times(&unit(ExtendedChar::Char('a')), &unit(ExtendedChar::Char('b'))));
-        test_within_bounds(&another_nfa);
-        let mut stream = CharStream::from_string(String::from("abb"));
-        assert!(!another_nfa.is_match(&mut stream));
+    pub fn test_split() {
+        let comma = unit(ExtendedChar::Char(','));
+        assert_eq!(comma.split("a,b,,c"), vec!["a", "b", "", "c"]);
+        assert_eq!(comma.split(",a,"), vec!["", "a", ""]);
+        assert_eq!(comma.split(""), vec![""]);
+        assert_eq!(literal("日").split("a日b"), vec!["a", "b"]);
+        assert_eq!(empty().split("ab"), vec!["", "a", "b", ""]);
+    }
+
+    #[test]
+    pub fn test_shortest_match() {
+        let ab = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        assert_eq!(ab.shortest_match(), Some(String::from("ab")));
+        assert_eq!(
+            plus(&literal("abc"), &literal("xy")).shortest_match(),
+            Some(String::from("xy"))
+        );
+        assert_eq!(star(&ab).shortest_match(), Some(String::new()));
+        assert_eq!(
+            times(&dot(), &literal("b")).shortest_match(),
+            Some(String::from("bb"))
+        );
+        assert_eq!(union(&[]).shortest_match(), None);
+        assert_eq!(times(&ab, &start_anchor()).shortest_match(), None);
+        assert_eq!(times(&end_anchor(), &ab).shortest_match(), None);
+        assert_eq!(
+            times(&times(&start_anchor(), &ab), &end_anchor()).shortest_match(),
+            Some(String::from("ab"))
+        );
+    }
+
+    #[test]
+    pub fn test_is_subset() {
+        let ab_star = star(&plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        ));
+        let alphabet = ['a', 'b'];
+        assert!(is_subset(&literal("ab"), &ab_star, &alphabet));
+        assert!(!is_subset(&ab_star, &literal("ab"), &alphabet));
+        assert!(is_subset(&union(&[]), &literal("ab"), &alphabet));
+        assert!(is_subset(&ab_star, &ab_star, &alphabet));
+        let a_end_b = times(&times(&literal("a"), &end_anchor()), &literal("b"));
+        assert!(is_subset(&a_end_b, &nothing(), &alphabet));
+        assert!(equivalent(
+            &plus(&a_end_b, &literal("c")),
+            &literal("c"),
+            &['a', 'b', 'c']
+        ));
+        // only `b` is complemented, so `a` may have predicate edges
+        assert!(is_subset(&digit(), &dot(), &['1']));
+    }
+
+    #[test]
+    #[should_panic(expected = "predicate edges can't be determinized")]
+    pub fn test_is_subset_of_predicate() {
+        is_subset(&dot(), &digit(), &['1']);
+    }
+
+    #[test]
+    pub fn test_is_deterministic() {
+        let a = unit(ExtendedChar::Char('a'));
+        assert!(a.is_deterministic());
+        assert!(dot().is_deterministic());
+        assert!(!plus(&a, &a).is_deterministic());
+        assert!(!literal("ab").is_deterministic());
+        assert!(literal("ab").canonicalize().is_deterministic());
+        assert!(plus(&a, &dot()).canonicalize().is_deterministic());
+        assert!(!plus(&literal("ab"), &dot()).is_deterministic());
+        assert!(plus(&literal("ab"), &dot())
+            .canonicalize()
+            .is_deterministic());
+        assert!(none_of(&['a']).is_deterministic());
+        assert!(!start_anchor().is_deterministic());
+    }
+
+    #[test]
+    pub fn test_run() {
+        let nfa = times(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        assert_eq!(nfa.run(""), [Node(0)].into());
+        assert_eq!(nfa.run("a"), [Node(1), Node(2)].into());
+        let after = nfa.run("ab");
+        assert_eq!(after, [Node(3)].into());
+        assert!(after.iter().any(|&node| nfa.is_final(node)));
+        assert!(nfa.run("b").is_empty());
     }
 }
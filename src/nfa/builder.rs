@@ -0,0 +1,221 @@
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, NFA};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Hand-built NFA with explicit state numbers. Nodes are handed out by
+/// `add_state` in order from `Node(0)`; `build` checks that every node
+/// referenced by a start, final or transition was actually added.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Default)]
+pub struct NFABuilder {
+    states: usize,
+    starting: HashSet<Node>,
+    delta: HashMap<(Node, ExtendedChar), HashSet<Node>>,
+    finished: HashSet<Node>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BuildError {
+    /// A node at or beyond the number of added states was referenced.
+    NodeOutOfBounds { node: Node, states: usize },
+    /// A deterministic table moves from `state` on `ch` to more than one
+    /// state.
+    ConflictingTransition { state: usize, ch: char },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::NodeOutOfBounds {
+                node: Node(n),
+                states,
+            } => {
+                write!(f, "node {} is out of bounds for {} states", n, states)
+            }
+            BuildError::ConflictingTransition { state, ch } => {
+                write!(
+                    f,
+                    "state {} has more than one transition on {:?}",
+                    state, ch
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl NFABuilder {
+    pub fn new() -> NFABuilder {
+        NFABuilder::default()
+    }
+
+    pub fn add_state(&mut self) -> Node {
+        self.states += 1;
+        Node(self.states - 1)
+    }
+
+    pub fn mark_start(&mut self, node: Node) -> &mut NFABuilder {
+        self.starting.insert(node);
+        self
+    }
+
+    pub fn mark_final(&mut self, node: Node) -> &mut NFABuilder {
+        self.finished.insert(node);
+        self
+    }
+
+    pub fn add_transition(&mut self, from: Node, ch: char, to: Node) -> &mut NFABuilder {
+        self.delta
+            .entry((from, ExtendedChar::Char(ch)))
+            .or_default()
+            .insert(to);
+        self
+    }
+
+    pub fn build(&self) -> Result<NFA, BuildError> {
+        let referenced = self
+            .starting
+            .iter()
+            .chain(self.finished.iter())
+            .chain(self.delta.keys().map(|(node, _)| node))
+            .chain(self.delta.values().flatten());
+        check_bounds(self.states, referenced)?;
+        Ok(NFA {
+            states: self.states,
+            starting: self.starting.clone(),
+            delta: self.delta.clone(),
+            epsilon: HashMap::new(),
+            finished: self.finished.clone(),
+            labels: [].into(),
+            branches: [].into(),
+        })
+    }
+}
+
+/// Fails on the first of `nodes` that isn't below `states`.
+pub(crate) fn check_bounds<'a>(
+    states: usize,
+    nodes: impl IntoIterator<Item = &'a Node>,
+) -> Result<(), BuildError> {
+    match nodes.into_iter().find(|&&Node(n)| n >= states) {
+        Some(&node) => Err(BuildError::NodeOutOfBounds { node, states }),
+        None => Ok(()),
+    }
+}
+
+/// Imports a deterministic automaton given as a table, e.g. one produced by
+/// another tool. States are numbered `0..num_states`; each becomes the node
+/// with the same number, with a singleton successor set per transition.
+/// Fails if a state is out of bounds or if a state has two different
+/// transitions on the same character.
+pub fn from_dfa_table(
+    num_states: usize,
+    start: usize,
+    finals: &[usize],
+    transitions: &[(usize, char, usize)],
+) -> Result<NFA, BuildError> {
+    let mut targets: HashMap<(usize, char), usize> = HashMap::new();
+    for &(from, ch, to) in transitions {
+        if *targets.entry((from, ch)).or_insert(to) != to {
+            return Err(BuildError::ConflictingTransition { state: from, ch });
+        }
+    }
+    let mut builder = NFABuilder::new();
+    for _ in 0..num_states {
+        builder.add_state();
+    }
+    builder.mark_start(Node(start));
+    for &state in finals {
+        builder.mark_final(Node(state));
+    }
+    for &(from, ch, to) in transitions {
+        builder.add_transition(Node(from), ch, Node(to));
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::builder::*;
+
+    #[test]
+    pub fn test_build() {
+        let mut builder = NFABuilder::new();
+        let start = builder.add_state();
+        let end = builder.add_state();
+        builder
+            .mark_start(start)
+            .mark_final(end)
+            .add_transition(start, 'a', end)
+            .add_transition(end, 'b', start);
+        let nfa = builder.build().unwrap();
+        assert_eq!(start, Node(0));
+        assert_eq!(end, Node(1));
+        assert!(nfa.is_match("a"));
+        assert!(nfa.is_match("aba"));
+        assert!(!nfa.is_match("ab"));
+        assert!(!nfa.is_match(""));
+    }
+
+    #[test]
+    pub fn test_build_rejects_out_of_bounds() {
+        let mut builder = NFABuilder::new();
+        let start = builder.add_state();
+        builder
+            .mark_start(start)
+            .add_transition(start, 'a', Node(1));
+        assert_eq!(
+            builder.build().unwrap_err(),
+            BuildError::NodeOutOfBounds {
+                node: Node(1),
+                states: 1
+            }
+        );
+        let mut builder = NFABuilder::new();
+        builder.mark_final(Node(0));
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    pub fn test_from_dfa_table_round_trip() {
+        let nfa = crate::parser::parse("(ab|c)*d").unwrap();
+        let dfa = nfa.to_dfa();
+        assert!(dfa.wildcard_transitions().is_empty());
+        let finals: Vec<usize> = (0..dfa.num_states())
+            .filter(|&state| dfa.is_final(state))
+            .collect();
+        let imported = from_dfa_table(
+            dfa.num_states(),
+            dfa.start_state(),
+            &finals,
+            &dfa.transitions(),
+        )
+        .unwrap();
+        assert!(imported.is_deterministic());
+        assert!(crate::nfa::equivalent(
+            &imported,
+            &nfa,
+            &['a', 'b', 'c', 'd']
+        ));
+    }
+
+    #[test]
+    pub fn test_from_dfa_table_rejects_bad_tables() {
+        assert_eq!(
+            from_dfa_table(2, 0, &[1], &[(0, 'a', 2)]).unwrap_err(),
+            BuildError::NodeOutOfBounds {
+                node: Node(2),
+                states: 2
+            }
+        );
+        assert!(from_dfa_table(1, 1, &[], &[]).is_err());
+        assert!(from_dfa_table(1, 0, &[3], &[]).is_err());
+        assert_eq!(
+            from_dfa_table(2, 0, &[1], &[(0, 'a', 1), (0, 'a', 0)]).unwrap_err(),
+            BuildError::ConflictingTransition { state: 0, ch: 'a' }
+        );
+        assert!(from_dfa_table(2, 0, &[1], &[(0, 'a', 1), (0, 'a', 1)]).is_ok());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, NFA};
+use std::collections::HashMap;
+
+/// A regular expression over the syntax accepted by `parser::parse`, kept
+/// just structured enough to print with the fewest parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Epsilon,
+    Symbol(String),
+    Alt(Vec<Expr>),
+    Cat(Vec<Expr>),
+    Star(Box<Expr>),
+}
+
+impl NFA {
+    /// Converts the automaton into a pattern that `parser::parse` turns back
+    /// into an equivalent automaton, by state elimination: nodes are removed
+    /// one at a time, relabelling the edges around each with expressions.
+    /// The pattern is correct but far from minimal. The empty language comes
+    /// out as `$.`, and `NoneOf` edges as `[^...]` classes with `]`, `\` and
+    /// `-` escaped. Panics if the automaton has `Predicate` edges, which the
+    /// pattern syntax can't express.
+    pub fn to_regex(&self) -> String {
+        let start = self.states;
+        let end = self.states + 1;
+        // sorted, so that the same automaton always prints the same pattern
+        let mut labelled: Vec<(usize, usize, &ExtendedChar)> = Vec::new();
+        for ((Node(from), ec), set) in self.delta.iter() {
+            labelled.extend(set.iter().map(|&Node(to)| (*from, to, ec)));
+        }
+        labelled.sort();
+        let mut unlabelled: Vec<(usize, usize)> = Vec::new();
+        for (Node(from), set) in self.epsilon.iter() {
+            unlabelled.extend(set.iter().map(|&Node(to)| (*from, to)));
+        }
+        unlabelled.extend(self.starting.iter().map(|&Node(n)| (start, n)));
+        unlabelled.extend(self.finished.iter().map(|&Node(n)| (n, end)));
+        unlabelled.sort();
+
+        let mut edges: HashMap<(usize, usize), Expr> = HashMap::new();
+        for (from, to, ec) in labelled {
+            add(&mut edges, from, to, symbol(ec));
+        }
+        for (from, to) in unlabelled {
+            add(&mut edges, from, to, Expr::Epsilon);
+        }
+
+        for k in 0..self.states {
+            let lo = edges.remove(&(k, k)).map(star);
+            let incoming: Vec<(usize, Expr)> = (0..=end)
+                .filter_map(|i| edges.remove(&(i, k)).map(|expr| (i, expr)))
+                .collect();
+            let outgoing: Vec<(usize, Expr)> = (0..=end)
+                .filter_map(|j| edges.remove(&(k, j)).map(|expr| (j, expr)))
+                .collect();
+            for (i, before) in incoming.iter() {
+                for (j, after) in outgoing.iter() {
+                    let mut path = before.clone();
+                    if let Some(lo) = &lo {
+                        path = cat(path, lo.clone());
+                    }
+                    add(&mut edges, *i, *j, cat(path, after.clone()));
+                }
+            }
+        }
+        match edges.remove(&(start, end)) {
+            Some(expr) => print(&expr, 0),
+            None => String::from("$."),
+        }
+    }
+}
+
+fn add(edges: &mut HashMap<(usize, usize), Expr>, from: usize, to: usize, expr: Expr) {
+    let merged = match edges.remove(&(from, to)) {
+        Some(existing) => alt(existing, expr),
+        None => expr,
+    };
+    edges.insert((from, to), merged);
+}
+
+fn symbol(ec: &ExtendedChar) -> Expr {
+    Expr::Symbol(match ec {
+        ExtendedChar::Char(ch) if "()[|*.^$\\".contains(*ch) => format!("\\{}", ch),
+        ExtendedChar::Char(ch) => ch.to_string(),
+        ExtendedChar::Wildcard => String::from("."),
+        ExtendedChar::StartOfInput => String::from("^"),
+        ExtendedChar::EndOfInput => String::from("$"),
+        ExtendedChar::NoneOf(excluded) => {
+            let inner: String = excluded
+                .iter()
+                .map(|&ch| match ch {
+                    ']' | '\\' | '-' => format!("\\{}", ch),
+                    _ => ch.to_string(),
+                })
+                .collect();
+            format!("[^{}]", inner)
+        }
+        ExtendedChar::Predicate(_) => {
+            panic!("{} has no equivalent in the pattern syntax", ec)
+        }
+    })
+}
+
+fn alt(first: Expr, second: Expr) -> Expr {
+    let mut options = Vec::new();
+    for expr in [first, second] {
+        match expr {
+            Expr::Alt(inner) => options.extend(inner),
+            other => options.push(other),
+        }
+    }
+    let mut unique: Vec<Expr> = Vec::new();
+    for option in options {
+        if !unique.contains(&option) {
+            unique.push(option);
+        }
+    }
+    if unique.len() == 1 {
+        unique.pop().unwrap()
+    } else {
+        Expr::Alt(unique)
+    }
+}
+
+fn cat(first: Expr, second: Expr) -> Expr {
+    let mut parts = Vec::new();
+    for expr in [first, second] {
+        match expr {
+            Expr::Epsilon => {}
+            Expr::Cat(inner) => parts.extend(inner),
+            other => parts.push(other),
+        }
+    }
+    match parts.len() {
+        0 => Expr::Epsilon,
+        1 => parts.pop().unwrap(),
+        _ => Expr::Cat(parts),
+    }
+}
+
+fn star(expr: Expr) -> Expr {
+    match expr {
+        Expr::Epsilon => Expr::Epsilon,
+        Expr::Star(_) => expr,
+        other => Expr::Star(Box::new(other)),
+    }
+}
+
+/// Prints `expr` where the surrounding context binds at `level`: 0 for an
+/// alternative, 1 for a part of a concatenation, 2 for a starred atom.
+fn print(expr: &Expr, level: u8) -> String {
+    match expr {
+        Expr::Epsilon if level == 0 => String::new(),
+        Expr::Epsilon => String::from("()"),
+        Expr::Symbol(s) => s.clone(),
+        Expr::Alt(options) => {
+            let inner: Vec<String> = options.iter().map(|option| print(option, 0)).collect();
+            parenthesize(inner.join("|"), level > 0)
+        }
+        Expr::Cat(parts) => {
+            let inner: String = parts.iter().map(|part| print(part, 1)).collect();
+            parenthesize(inner, level > 1)
+        }
+        Expr::Star(inner) => format!("{}*", print(inner, 2)),
+    }
+}
+
+fn parenthesize(s: String, needed: bool) -> String {
+    if needed {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::*;
+    use crate::parser::parse;
+
+    #[test]
+    pub fn test_to_regex_of_star() {
+        let nfa = star(&unit(ExtendedChar::Char('a')));
+        let pattern = nfa.to_regex();
+        assert!(equivalent(&parse(&pattern).unwrap(), &nfa, &['a', 'b']));
+    }
+
+    #[test]
+    pub fn test_to_regex_round_trips() {
+        let patterns = [
+            "(ab|c)*d",
+            "a.c",
+            "^(a|b)*a$",
+            "",
+            "a|",
+            "\\(\\*\\)|\\.\\\\",
+            "日(本|.)*",
+            "(a*b*)*c",
+            "[^a]b|\\[|[[]]",
+            "[^a\\-]-|[a-c]",
+        ];
+        for pattern in patterns {
+            let nfa = parse(pattern).unwrap();
+            let exported = nfa.to_regex();
+            let reparsed = parse(&exported).unwrap();
+            let inputs = [
+                "", "a", "ab", "abcd", "d", "ac", "abc", "aba", "bb", "c", "abbac", "日本",
+                "日x本", "(*)", ".\\", "()", "[", "]b", "[]", "-", "b-", "--",
+            ];
+            for input in inputs {
+                assert_eq!(
+                    reparsed.is_match(input),
+                    nfa.is_match(input),
+                    "{:?} exported as {:?} on {:?}",
+                    pattern,
+                    exported,
+                    input
+                );
+            }
+        }
+        let nfa = parse("(ab|c)*d").unwrap();
+        assert_eq!(nfa.to_regex(), parse("(ab|c)*d").unwrap().to_regex());
+        let nothing = union(&[]).to_regex();
+        assert_eq!(nothing, "$.");
+        assert!(!parse(&nothing).unwrap().is_match(""));
+        assert!(!parse(&nothing).unwrap().is_match("a"));
+    }
+
+    #[test]
+    pub fn test_to_regex_of_canonical() {
+        // canonicalizing gives the states with both exact and wildcard moves
+        // a `NoneOf` edge
+        let nfa = plus(
+            &times(&literal("a]"), &star_fresh(&dot())),
+            &times(&none_of(&['\\']), &literal("b")),
+        );
+        let canonical = nfa.canonicalize();
+        assert!(canonical
+            .delta
+            .keys()
+            .any(|(_, ec)| matches!(ec, ExtendedChar::NoneOf(_))));
+        let reparsed = parse(&canonical.to_regex()).unwrap();
+        assert!(equivalent(&reparsed, &nfa, &['a', ']', '\\', 'b']));
+        for input in ["a]", "a]\\", "ab", "]b", "\\b", "a", "日b", "b"] {
+            assert_eq!(reparsed.is_match(input), nfa.is_match(input), "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, MatchError, ScannedEdges, NFA};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+
+/// A configuration of the search: a node together with the byte offset of
+/// the next character to consume.
+type Config = (Node, usize);
+
+impl NFA {
+    /// Like `is_match`, but explores configurations on a pool of `threads`
+    /// workers (at least one). The workers share a queue of pending
+    /// configurations and a memo of the ones already seen, so no
+    /// configuration is expanded twice, and stop as soon as one of them
+    /// reaches a finished node at the end of `s`. Not available on wasm32.
+    ///
+    /// Panics if a worker does; see `try_is_match_parallel`.
+    pub fn is_match_parallel(&self, s: &str, threads: usize) -> bool {
+        self.try_is_match_parallel(s, threads)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `is_match_parallel`, but reports a worker that panicked, or a
+    /// lock it poisoned, as an error instead. The other workers stop as soon
+    /// as one of them fails.
+    pub fn try_is_match_parallel(&self, s: &str, threads: usize) -> Result<bool, MatchError> {
+        let starting: Vec<Config> = self.starting.iter().map(|&node| (node, 0)).collect();
+        let search = Search {
+            nfa: self,
+            s,
+            scanned: self.scanned_edges(),
+            seen_configs: Mutex::new(starting.iter().copied().collect()),
+            in_flight: AtomicUsize::new(starting.len()),
+            queue: Mutex::new(starting),
+            ready: Condvar::new(),
+            found: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+        };
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.max(1))
+                .map(|_| scope.spawn(|| search.work()))
+                .collect();
+            // join every worker, so that none of their panics reaches `scope`
+            let results: Vec<Result<(), MatchError>> = workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap_or(Err(MatchError::WorkerPanicked)))
+                .collect();
+            results.into_iter().collect::<Result<(), MatchError>>()
+        })?;
+        Ok(search.found.into_inner())
+    }
+}
+
+struct Search<'a> {
+    nfa: &'a NFA,
+    s: &'a str,
+    scanned: ScannedEdges,
+    queue: Mutex<Vec<Config>>,
+    // idle workers wait here, paired with `queue`; notified when
+    // configurations are queued and when the search is over
+    ready: Condvar,
+    seen_configs: Mutex<HashSet<Config>>,
+    // configurations queued or being expanded; the search is over at zero
+    in_flight: AtomicUsize,
+    found: AtomicBool,
+    // set by a worker that fails, so the others stop instead of waiting on
+    // configurations it will never finish
+    failed: AtomicBool,
+}
+
+/// Marks the search as failed if the worker holding it unwinds.
+struct FailOnPanic<'a, 'b>(&'a Search<'b>);
+
+impl Drop for FailOnPanic<'_, '_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.stop(&self.0.failed);
+        }
+    }
+}
+
+fn locked<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>, MatchError> {
+    mutex.lock().map_err(|_| MatchError::Poisoned)
+}
+
+impl Search<'_> {
+    fn work(&self) -> Result<(), MatchError> {
+        let _guard = FailOnPanic(self);
+        let result = self.run();
+        if result.is_err() {
+            self.stop(&self.failed);
+        }
+        result
+    }
+
+    fn run(&self) -> Result<(), MatchError> {
+        loop {
+            let mut queue = locked(&self.queue)?;
+            let config = loop {
+                if self.found.load(Ordering::Acquire) || self.failed.load(Ordering::Acquire) {
+                    return Ok(());
+                }
+                if let Some(config) = queue.pop() {
+                    break config;
+                }
+                if self.in_flight.load(Ordering::Acquire) == 0 {
+                    return Ok(());
+                }
+                queue = self.ready.wait(queue).map_err(|_| MatchError::Poisoned)?;
+            };
+            drop(queue);
+            self.expand(config)?;
+            if self.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+                self.wake_all();
+            }
+        }
+    }
+
+    /// Sets `flag` and wakes the idle workers so they see it.
+    fn stop(&self, flag: &AtomicBool) {
+        flag.store(true, Ordering::Release);
+        self.wake_all();
+    }
+
+    fn wake_all(&self) {
+        // taking the lock orders the notification after any worker that is
+        // between its checks and its wait, so none of them misses it; a
+        // poisoned lock orders it just as well
+        drop(self.queue.lock());
+        self.ready.notify_all();
+    }
+
+    fn expand(&self, (node, at): Config) -> Result<(), MatchError> {
+        let nfa = self.nfa;
+        if at == self.s.len() && nfa.finished.contains(&node) {
+            self.stop(&self.found);
+            return Ok(());
+        }
+        let edge = |ec: ExtendedChar| nfa.delta.get(&(node, ec)).into_iter().flatten();
+        let mut next: Vec<Config> = nfa
+            .epsilon
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .map(|&target| (target, at))
+            .collect();
+        if at == 0 {
+            next.extend(edge(ExtendedChar::StartOfInput).map(|&target| (target, at)));
+        }
+        if at == self.s.len() {
+            next.extend(edge(ExtendedChar::EndOfInput).map(|&target| (target, at)));
+        }
+        if let Some(ch) = self.s[at..].chars().next() {
+            let after = at + ch.len_utf8();
+            let targets = edge(ExtendedChar::Char(ch)).chain(edge(ExtendedChar::Wildcard));
+            next.extend(targets.map(|&target| (target, after)));
+            next.extend(self.scanned.targets(node, ch).map(|target| (target, after)));
+        }
+
+        let mut seen = locked(&self.seen_configs)?;
+        next.retain(|&config| seen.insert(config));
+        drop(seen);
+        if !next.is_empty() {
+            self.in_flight.fetch_add(next.len(), Ordering::AcqRel);
+            locked(&self.queue)?.extend(next);
+            self.ready.notify_all();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::parallel::*;
+    use crate::nfa::test::Lcg;
+    use crate::nfa::*;
+
+    #[test]
+    pub fn test_is_match_parallel_agrees_with_is_match() {
+        let ab = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let nfas = [
+            times(
+                &star_fresh(&ab),
+                &times(&unit(ExtendedChar::Char('a')), &dot()),
+            ),
+            times(&times(&start_anchor(), &literal("ab")), &end_anchor()),
+            union(&[literal("cat"), literal("日本"), empty()]),
+            times(&none_of(&['b', 'c']), &star_fresh(&dot())),
+            union(&[]),
+        ];
+        let inputs = ["", "a", "ab", "abab", "abba", "bab", "cat", "日本", "x"];
+        for threads in 1..=8 {
+            for nfa in nfas.iter() {
+                for input in inputs {
+                    assert_eq!(
+                        nfa.is_match_parallel(input, threads),
+                        nfa.is_match(input),
+                        "{} threads on {:?}",
+                        threads,
+                        input
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_is_match_parallel_agrees_on_random_inputs() {
+        let ab = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let nfas = [
+            // the third character from the end is an 'a'
+            times(&star_fresh(&ab), &times(&literal("a"), &times(&ab, &ab))),
+            times(
+                &times(&start_anchor(), &star_fresh(&literal("ab"))),
+                &end_anchor(),
+            ),
+            times(&none_of(&['c']), &star_fresh(&plus(&literal("ca"), &dot()))),
+        ];
+        let mut rng = Lcg(0x5eed);
+        for _ in 0..3000 {
+            let len = rng.below(9);
+            let input: String = (0..len)
+                .map(|_| ['a', 'b', 'c'][rng.below(3) as usize])
+                .collect();
+            let threads = 1 + rng.below(4) as usize;
+            for nfa in nfas.iter() {
+                assert_eq!(
+                    nfa.is_match_parallel(&input, threads),
+                    nfa.is_match(&input),
+                    "{} threads on {:?}",
+                    threads,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_is_match_parallel_empty() {
+        // the empty input is decided by the starting configurations alone
+        for threads in 1..=4 {
+            assert!(empty().is_match_parallel("", threads));
+            assert!(star_fresh(&literal("a")).is_match_parallel("", threads));
+            assert!(!union(&[]).is_match_parallel("", threads));
+        }
+    }
+
+    #[test]
+    pub fn test_is_match_parallel_nonempty_rejects() {
+        for threads in 1..=4 {
+            assert!(!unit(ExtendedChar::Char('a')).is_match_parallel("", threads));
+            assert!(!empty().is_match_parallel("a", threads));
+        }
+    }
+
+    #[test]
+    pub fn test_try_is_match_parallel_reports_panicking_worker() {
+        let exploding = matching(|ch| {
+            if ch == 'x' {
+                panic!("predicate rejects 'x' loudly");
+            }
+            ch == 'a'
+        });
+        let nfa = star_fresh(&exploding);
+        for threads in 1..=4 {
+            assert_eq!(nfa.try_is_match_parallel("aaa", threads), Ok(true));
+            assert_eq!(
+                nfa.try_is_match_parallel("aax", threads),
+                Err(MatchError::WorkerPanicked)
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_try_is_match_parallel_reports_poisoned_lock() {
+        let nfa = literal("ab");
+        let search = Search {
+            nfa: &nfa,
+            s: "ab",
+            scanned: nfa.scanned_edges(),
+            queue: Mutex::new(vec![(Node(0), 0)]),
+            ready: Condvar::new(),
+            seen_configs: Mutex::new(HashSet::new()),
+            in_flight: AtomicUsize::new(1),
+            found: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+        };
+        thread::scope(|scope| {
+            let poisoner = scope.spawn(|| {
+                let _held = search.queue.lock().unwrap();
+                panic!("poisoning the queue");
+            });
+            assert!(poisoner.join().is_err());
+        });
+        assert_eq!(search.work(), Err(MatchError::Poisoned));
+        assert!(search.failed.load(Ordering::Acquire));
+    }
+
+    #[test]
+    pub fn test_is_match_parallel_borrows_its_input() {
+        // neither the automaton nor the input is 'static
+        let nfa = literal(&"ab".repeat(3));
+        let input = String::from("ab") + "abab";
+        assert!(nfa.is_match_parallel(&input, 4));
+        assert!(!nfa.is_match_parallel(&input[2..], 4));
+    }
+}
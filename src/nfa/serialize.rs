@@ -0,0 +1,221 @@
+//! Serde representations for the `NFA` fields. JSON objects need string keys,
+//! so the maps are written as lists of entries, and every set and list is
+//! sorted so the same automaton always serializes identically.
+
+use crate::nfa::builder::{check_bounds, BuildError};
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, NFA};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+
+fn sorted(set: &HashSet<Node>) -> Vec<Node> {
+    let mut nodes: Vec<Node> = set.iter().copied().collect();
+    nodes.sort();
+    nodes
+}
+
+pub(crate) mod nodes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(set: &HashSet<Node>, serializer: S) -> Result<S::Ok, S::Error> {
+        sorted(set).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashSet<Node>, D::Error> {
+        Ok(Vec::<Node>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+pub(crate) mod delta {
+    use super::*;
+
+    type Delta = HashMap<(Node, ExtendedChar), HashSet<Node>>;
+
+    pub fn serialize<S: Serializer>(delta: &Delta, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<(Node, ExtendedChar, Vec<Node>)> = delta
+            .iter()
+            .map(|((node, ec), set)| (*node, ec.clone(), sorted(set)))
+            .collect();
+        entries.sort();
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Delta, D::Error> {
+        let entries = Vec::<(Node, ExtendedChar, Vec<Node>)>::deserialize(deserializer)?;
+        Ok(entries
+            .into_iter()
+            .map(|(node, ec, set)| ((node, ec), set.into_iter().collect()))
+            .collect())
+    }
+}
+
+pub(crate) mod epsilon {
+    use super::*;
+
+    type Epsilon = HashMap<Node, HashSet<Node>>;
+
+    pub fn serialize<S: Serializer>(epsilon: &Epsilon, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<(Node, Vec<Node>)> = epsilon
+            .iter()
+            .map(|(&node, set)| (node, sorted(set)))
+            .collect();
+        entries.sort();
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Epsilon, D::Error> {
+        let entries = Vec::<(Node, Vec<Node>)>::deserialize(deserializer)?;
+        Ok(entries
+            .into_iter()
+            .map(|(node, set)| (node, set.into_iter().collect()))
+            .collect())
+    }
+}
+
+/// A map from nodes to plain values, such as `labels` and `branches`.
+pub(crate) mod node_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Serialize + Ord + Copy>(
+        map: &HashMap<Node, T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<(Node, T)> = map.iter().map(|(&node, &value)| (node, value)).collect();
+        entries.sort();
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Node, T>, D::Error> {
+        Ok(Vec::<(Node, T)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// An `NFA` as read, before its nodes are checked against `states`, so that
+/// malformed input fails to deserialize instead of panicking once the
+/// automaton is run.
+#[derive(Deserialize)]
+pub(crate) struct Unchecked {
+    states: usize,
+    #[serde(with = "nodes")]
+    starting: HashSet<Node>,
+    #[serde(with = "delta")]
+    delta: HashMap<(Node, ExtendedChar), HashSet<Node>>,
+    #[serde(with = "epsilon")]
+    epsilon: HashMap<Node, HashSet<Node>>,
+    #[serde(with = "nodes")]
+    finished: HashSet<Node>,
+    #[serde(default, with = "node_map")]
+    labels: HashMap<Node, u32>,
+    #[serde(default, with = "node_map")]
+    branches: HashMap<Node, usize>,
+}
+
+impl TryFrom<Unchecked> for NFA {
+    type Error = BuildError;
+
+    fn try_from(nfa: Unchecked) -> Result<NFA, BuildError> {
+        let referenced = nfa
+            .starting
+            .iter()
+            .chain(nfa.finished.iter())
+            .chain(nfa.delta.keys().map(|(node, _)| node))
+            .chain(nfa.delta.values().flatten())
+            .chain(nfa.epsilon.keys())
+            .chain(nfa.epsilon.values().flatten())
+            .chain(nfa.labels.keys())
+            .chain(nfa.branches.keys());
+        check_bounds(nfa.states, referenced)?;
+        Ok(NFA {
+            states: nfa.states,
+            starting: nfa.starting,
+            delta: nfa.delta,
+            epsilon: nfa.epsilon,
+            finished: nfa.finished,
+            labels: nfa.labels,
+            branches: nfa.branches,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::node::Node;
+    use crate::nfa::*;
+
+    #[test]
+    pub fn test_node_is_bare_integer() {
+        assert_eq!(serde_json::to_string(&Node(3)).unwrap(), "3");
+        assert_eq!(serde_json::from_str::<Node>("7").unwrap(), Node(7));
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        let nfa = times(
+            &star(&times(
+                &unit(ExtendedChar::Char('a')),
+                &unit(ExtendedChar::Char('b')),
+            )),
+            &unit(ExtendedChar::Char('c')),
+        );
+        let json = serde_json::to_string(&nfa).unwrap();
+        assert_eq!(json, serde_json::to_string(&nfa).unwrap());
+        let restored: NFA = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, nfa);
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        for input in ["", "c", "abc", "ababc", "ab", "abcc", "bac", "abac"] {
+            assert_eq!(
+                restored.is_match(input),
+                nfa.is_match(input),
+                "on {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_json_shape() {
+        let json = serde_json::to_string(&unit(ExtendedChar::Char('a'))).unwrap();
+        assert_eq!(
+            json,
+            r#"{"states":2,"starting":[0],"delta":[[0,{"Char":"a"},[1]]],"epsilon":[],"finished":[1]}"#
+        );
+        let labeled = labeled(&unit(ExtendedChar::Char('a')), 4);
+        let json = serde_json::to_string(&labeled).unwrap();
+        assert!(json.ends_with(r#""finished":[1],"labels":[[1,4]]}"#));
+        assert_eq!(serde_json::from_str::<NFA>(&json).unwrap(), labeled);
+    }
+
+    #[test]
+    pub fn test_out_of_bounds_nodes_fail_to_deserialize() {
+        for json in [
+            r#"{"states":1,"starting":[0],"delta":[[0,{"Char":"a"},[3]]],"epsilon":[],"finished":[0]}"#,
+            r#"{"states":1,"starting":[0],"delta":[[2,"Wildcard",[0]]],"epsilon":[],"finished":[0]}"#,
+            r#"{"states":2,"starting":[0],"delta":[],"epsilon":[[0,[1,5]]],"finished":[1]}"#,
+            r#"{"states":2,"starting":[0],"delta":[],"epsilon":[],"finished":[2]}"#,
+            r#"{"states":0,"starting":[0],"delta":[],"epsilon":[],"finished":[]}"#,
+            r#"{"states":1,"starting":[0],"delta":[],"epsilon":[],"finished":[0],"labels":[[1,4]]}"#,
+            r#"{"states":1,"starting":[0],"delta":[],"epsilon":[],"finished":[0],"branches":[[9,1]]}"#,
+        ] {
+            let error = serde_json::from_str::<NFA>(json).unwrap_err();
+            assert!(
+                error.to_string().contains("is out of bounds for"),
+                "{}: {}",
+                json,
+                error
+            );
+        }
+        let error = serde_json::from_str::<NFA>(
+            r#"{"states":1,"starting":[0],"delta":[[0,{"Char":"a"},[3]]],"epsilon":[],"finished":[0]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "node 3 is out of bounds for 1 states");
+    }
+}
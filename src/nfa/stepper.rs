@@ -0,0 +1,148 @@
+use crate::nfa::node::Node;
+use crate::nfa::{ExtendedChar, NFA};
+use std::collections::HashSet;
+
+/// Stepping tables for simulating an `NFA` over one input.
+///
+/// The outgoing edges of every node are indexed once up front. The first time
+/// a node becomes active its row is memoized, with the epsilon closure of each
+/// target set already taken, so a step costs one binary search per active node
+/// instead of hashing every node and recomputing closures.
+#[derive(Debug, Clone)]
+pub(crate) struct Stepper<'a> {
+    nfa: &'a NFA,
+    outgoing: Vec<Vec<(&'a ExtendedChar, &'a HashSet<Node>)>>,
+    rows: Vec<Option<Row<'a>>>,
+    finished: Vec<bool>,
+    // `seen[n] == generation` marks `n` as already added to the next set
+    seen: Vec<usize>,
+    generation: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Row<'a> {
+    exact: Vec<(char, Vec<usize>)>,
+    wildcard: Vec<usize>,
+    // `NoneOf` and `Predicate` edges, checked against every character
+    scanned: Vec<(&'a ExtendedChar, Vec<usize>)>,
+}
+
+impl<'a> Stepper<'a> {
+    pub(crate) fn new(nfa: &'a NFA) -> Stepper<'a> {
+        let mut outgoing = vec![Vec::new(); nfa.states];
+        for ((Node(n), ec), set) in nfa.delta.iter() {
+            outgoing[*n].push((ec, set));
+        }
+        Stepper {
+            nfa,
+            outgoing,
+            rows: (0..nfa.states).map(|_| None).collect(),
+            finished: (0..nfa.states)
+                .map(|n| nfa.finished.contains(&Node(n)))
+                .collect(),
+            seen: vec![0; nfa.states],
+            generation: 0,
+        }
+    }
+
+    /// The active nodes before any input, following start anchors only if
+    /// the position is also the start of the input.
+    pub(crate) fn start(&self, at_start: bool) -> Vec<usize> {
+        let closure = self.nfa.closure_at(&self.nfa.starting, at_start, false);
+        closure.into_iter().map(|Node(n)| n).collect()
+    }
+
+    pub(crate) fn step(&mut self, active: &[usize], ch: char) -> Vec<usize> {
+        self.generation += 1;
+        let mut next = Vec::new();
+        for &node in active {
+            self.fill_row(node);
+            let row = self.rows[node].as_ref().unwrap();
+            let exact = match row.exact.binary_search_by_key(&ch, |&(c, _)| c) {
+                Ok(i) => &row.exact[i].1[..],
+                Err(_) => &[],
+            };
+            let others = row
+                .scanned
+                .iter()
+                .filter(|(ec, _)| ec.matches(ch))
+                .flat_map(|(_, targets)| targets.iter());
+            for &target in exact.iter().chain(row.wildcard.iter()).chain(others) {
+                if self.seen[target] != self.generation {
+                    self.seen[target] = self.generation;
+                    next.push(target);
+                }
+            }
+        }
+        next
+    }
+
+    /// Whether `active` accepts at a position that is the start and/or the
+    /// end of the input, as with `NFA::accepts_at`.
+    pub(crate) fn accepts(&self, active: &[usize], at_start: bool, at_end: bool) -> bool {
+        if active.iter().any(|&n| self.finished[n]) {
+            return true;
+        }
+        (at_start || at_end)
+            && self
+                .nfa
+                .accepts_at(&active.iter().map(|&n| Node(n)).collect(), at_start, at_end)
+    }
+
+    fn fill_row(&mut self, node: usize) {
+        if self.rows[node].is_some() {
+            return;
+        }
+        let mut row = Row::default();
+        for &(ec, targets) in self.outgoing[node].iter() {
+            let mut closure: Vec<usize> = self
+                .nfa
+                .epsilon_closure(targets)
+                .into_iter()
+                .map(|Node(n)| n)
+                .collect();
+            closure.sort();
+            match ec {
+                ExtendedChar::Char(ch) => row.exact.push((*ch, closure)),
+                ExtendedChar::Wildcard => row.wildcard = closure,
+                ExtendedChar::NoneOf(_) | ExtendedChar::Predicate(_) => {
+                    row.scanned.push((ec, closure))
+                }
+                ExtendedChar::StartOfInput | ExtendedChar::EndOfInput => {}
+            }
+        }
+        row.exact.sort_by_key(|&(ch, _)| ch);
+        self.rows[node] = Some(row);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::stepper::Stepper;
+    use crate::nfa::*;
+
+    #[test]
+    pub fn test_step_agrees_with_step_set() {
+        let ab = plus(
+            &unit(ExtendedChar::Char('a')),
+            &unit(ExtendedChar::Char('b')),
+        );
+        let nfa = times(
+            &star_fresh(&ab),
+            &times(&dot(), &unit(ExtendedChar::Char('a'))),
+        );
+        let mut stepper = Stepper::new(&nfa);
+        let mut active = stepper.start(true);
+        let scanned = nfa.scanned_edges();
+        let mut nodes = nfa.epsilon_closure(&nfa.starting);
+        for ch in "abxaab".chars() {
+            active = stepper.step(&active, ch);
+            nodes = nfa.step_set(&nodes, ch, &scanned);
+            let mut expected: Vec<usize> = nodes.iter().map(|&Node(n)| n).collect();
+            expected.sort();
+            let mut actual = active.clone();
+            actual.sort();
+            assert_eq!(actual, expected);
+        }
+    }
+}
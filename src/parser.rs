@@ -0,0 +1,449 @@
+use crate::nfa::{
+    case_variants, class, class_ci, dot, empty, end_anchor, none_of, plus, star_fresh,
+    start_anchor, times, unit, unit_ci, ExtendedChar, NFA,
+};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses `pattern` into an NFA built from the combinators in `nfa`.
+///
+/// Supported syntax is concatenation, `|` alternation, `*`, `.` for any
+/// character, `^` and `$` anchors, grouping with parentheses and bracketed
+/// classes: `[abc]` for one of the listed characters, `[a-c]` for one in an
+/// inclusive range and `[^abc]` for any character not listed. A backslash
+/// makes the next character match literally, inside a class as well. Inside a
+/// class `-` only separates the ends of a range; anywhere else (first, last or
+/// right after a range) it is an error and has to be escaped as `\-`.
+pub fn parse(pattern: &str) -> Result<NFA, ParseError> {
+    parse_with(pattern, false)
+}
+
+/// Like `parse`, but every literal (escaped or not) matches either case, as
+/// with `unit_ci`.
+pub fn parse_ci(pattern: &str) -> Result<NFA, ParseError> {
+    parse_with(pattern, true)
+}
+
+fn parse_with(pattern: &str, case_insensitive: bool) -> Result<NFA, ParseError> {
+    let mut parser = Parser {
+        chars: pattern.char_indices().peekable(),
+        len: pattern.len(),
+        case_insensitive,
+    };
+    let nfa = parser.alternation()?;
+    match parser.chars.next() {
+        Some((offset, ')')) => Err(ParseError {
+            offset,
+            message: "unmatched ')'",
+        }),
+        Some((offset, _)) => Err(ParseError {
+            offset,
+            message: "unexpected character",
+        }),
+        None => Ok(nfa),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+    case_insensitive: bool,
+}
+
+impl Parser<'_> {
+    fn alternation(&mut self) -> Result<NFA, ParseError> {
+        let mut nfa = self.concatenation()?;
+        while let Some(&(_, '|')) = self.chars.peek() {
+            self.chars.next();
+            nfa = plus(&nfa, &self.concatenation()?);
+        }
+        Ok(nfa)
+    }
+
+    fn concatenation(&mut self) -> Result<NFA, ParseError> {
+        let mut nfa = empty();
+        while let Some(&(_, ch)) = self.chars.peek() {
+            if ch == '|' || ch == ')' {
+                break;
+            }
+            nfa = times(&nfa, &self.repetition()?);
+        }
+        Ok(nfa)
+    }
+
+    fn repetition(&mut self) -> Result<NFA, ParseError> {
+        let mut nfa = self.atom()?;
+        while let Some(&(_, '*')) = self.chars.peek() {
+            self.chars.next();
+            nfa = star_fresh(&nfa);
+        }
+        Ok(nfa)
+    }
+
+    fn atom(&mut self) -> Result<NFA, ParseError> {
+        match self.chars.next() {
+            Some((offset, '(')) => {
+                let nfa = self.alternation()?;
+                match self.chars.next() {
+                    Some((_, ')')) => Ok(nfa),
+                    _ => Err(ParseError {
+                        offset,
+                        message: "unmatched '('",
+                    }),
+                }
+            }
+            Some((offset, '*')) => Err(ParseError {
+                offset,
+                message: "nothing to repeat",
+            }),
+            Some((offset, '[')) => self.class(offset),
+            Some((_, '.')) => Ok(dot()),
+            Some((_, '^')) => Ok(start_anchor()),
+            Some((_, '$')) => Ok(end_anchor()),
+            Some((offset, '\\')) => match self.chars.next() {
+                Some((_, ch)) => Ok(self.literal(ch)),
+                None => Err(ParseError {
+                    offset,
+                    message: "trailing '\\'",
+                }),
+            },
+            Some((_, ch)) => Ok(self.literal(ch)),
+            None => Err(ParseError {
+                offset: self.len,
+                message: "unexpected end of pattern",
+            }),
+        }
+    }
+
+    /// The rest of a class whose `[` is at `offset`.
+    fn class(&mut self, offset: usize) -> Result<NFA, ParseError> {
+        let negated = matches!(self.chars.peek(), Some(&(_, '^')));
+        if negated {
+            self.chars.next();
+        }
+        let mut ranges = Vec::new();
+        while let Some(low) = self.class_member(offset)? {
+            let Some(&(dash, '-')) = self.chars.peek() else {
+                ranges.push((low, low));
+                continue;
+            };
+            self.chars.next();
+            let unfinished = ParseError {
+                offset: dash,
+                message: "unfinished range",
+            };
+            let high = self.class_member(offset)?.ok_or(unfinished)?;
+            if high < low {
+                return Err(ParseError {
+                    offset: dash,
+                    message: "range out of order",
+                });
+            }
+            ranges.push((low, high));
+        }
+        match (negated, self.case_insensitive) {
+            (false, false) => Ok(class(&ranges)),
+            (false, true) => Ok(class_ci(&ranges)),
+            (true, case_insensitive) => {
+                let members = ranges.into_iter().flat_map(|(low, high)| low..=high);
+                let excluded: Vec<char> = if case_insensitive {
+                    members.flat_map(case_variants).collect()
+                } else {
+                    members.collect()
+                };
+                Ok(none_of(&excluded))
+            }
+        }
+    }
+
+    /// The next character listed in the class whose `[` is at `offset`, or
+    /// `None` once its `]` is reached.
+    fn class_member(&mut self, offset: usize) -> Result<Option<char>, ParseError> {
+        let unmatched = ParseError {
+            offset,
+            message: "unmatched '['",
+        };
+        match self.chars.next() {
+            Some((_, ']')) => Ok(None),
+            Some((_, '\\')) => match self.chars.next() {
+                Some((_, ch)) => Ok(Some(ch)),
+                None => Err(unmatched),
+            },
+            Some((dash, '-')) => Err(ParseError {
+                offset: dash,
+                message: "'-' outside a range",
+            }),
+            Some((_, ch)) => Ok(Some(ch)),
+            None => Err(unmatched),
+        }
+    }
+
+    fn literal(&self, ch: char) -> NFA {
+        if self.case_insensitive {
+            unit_ci(ch)
+        } else {
+            unit(ExtendedChar::Char(ch))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::test::Lcg;
+    use crate::parser::*;
+
+    fn matches(nfa: &NFA, s: &str) -> bool {
+        nfa.is_match(s)
+    }
+
+    #[test]
+    pub fn test_parse_literal() {
+        let nfa = parse("abc").unwrap();
+        assert!(matches(&nfa, "abc"));
+        assert!(!matches(&nfa, "ab"));
+        assert!(!matches(&nfa, "abcd"));
+    }
+
+    #[test]
+    pub fn test_parse_empty_pattern() {
+        let nfa = parse("").unwrap();
+        assert!(matches(&nfa, ""));
+        assert!(!matches(&nfa, "a"));
+    }
+
+    #[test]
+    pub fn test_parse_alternation_and_star() {
+        let nfa = parse("(ab|c)*d").unwrap();
+        assert!(matches(&nfa, "d"));
+        assert!(matches(&nfa, "abd"));
+        assert!(matches(&nfa, "cabccd"));
+        assert!(!matches(&nfa, "abc"));
+        assert!(!matches(&nfa, "ad"));
+    }
+
+    #[test]
+    pub fn test_parse_escapes() {
+        let nfa = parse("a\\*\\|\\(").unwrap();
+        assert!(matches(&nfa, "a*|("));
+        assert!(!matches(&nfa, "a"));
+        assert!(!matches(&nfa, "aa|("));
+    }
+
+    #[test]
+    pub fn test_parse_errors() {
+        assert_eq!(
+            parse("ab)").unwrap_err(),
+            ParseError {
+                offset: 2,
+                message: "unmatched ')'"
+            }
+        );
+        assert_eq!(
+            parse("x(ab").unwrap_err(),
+            ParseError {
+                offset: 1,
+                message: "unmatched '('"
+            }
+        );
+        assert_eq!(parse("*a").unwrap_err().offset, 0);
+        assert_eq!(parse("a|*").unwrap_err().offset, 2);
+        assert_eq!(parse("ab\\").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    pub fn test_parse_dot() {
+        let nfa = parse("a.c").unwrap();
+        assert!(matches(&nfa, "abc"));
+        assert!(matches(&nfa, "a5c"));
+        assert!(!matches(&nfa, "ac"));
+        let nfa = parse("a\\.c").unwrap();
+        assert!(matches(&nfa, "a.c"));
+        assert!(!matches(&nfa, "abc"));
+    }
+
+    #[test]
+    pub fn test_parse_multibyte() {
+        let nfa = parse("caf(é|e)\\*日*").unwrap();
+        assert!(matches(&nfa, "café*"));
+        assert!(matches(&nfa, "cafe*日日"));
+        assert!(!matches(&nfa, "caf*"));
+        assert_eq!(parse("é)").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    pub fn test_parse_ci() {
+        let nfa = parse_ci("(ab|c)*d").unwrap();
+        assert!(matches(&nfa, "aBCd"));
+        assert!(matches(&nfa, "D"));
+        assert!(!matches(&nfa, "abe"));
+        assert!(!matches(&parse("ab").unwrap(), "AB"));
+    }
+
+    #[test]
+    pub fn test_parse_star_only_full_repetitions() {
+        let nfa = parse("(a*b)*").unwrap();
+        assert!(matches(&nfa, ""));
+        assert!(matches(&nfa, "aabb"));
+        assert!(!matches(&nfa, "a"));
+        assert!(!matches(&nfa, "aba"));
+    }
+
+    #[test]
+    pub fn test_parse_anchors() {
+        let nfa = parse("^ab$").unwrap();
+        assert!(matches(&nfa, "ab"));
+        assert_eq!(nfa.find("ab"), Some((0, 2)));
+        assert_eq!(nfa.find("xab"), None);
+        assert_eq!(nfa.find("abx"), None);
+        assert_eq!(parse("ab$").unwrap().find_all("abab"), vec![(2, 4)]);
+        assert!(matches(&parse("\\^\\$").unwrap(), "^$"));
+    }
+
+    #[test]
+    pub fn test_parse_classes() {
+        let nfa = parse("[ab]c|[^a\\]]").unwrap();
+        assert!(matches(&nfa, "ac"));
+        assert!(matches(&nfa, "bc"));
+        assert!(!matches(&nfa, "cc"));
+        assert!(matches(&nfa, "b"));
+        assert!(matches(&nfa, "日"));
+        assert!(!matches(&nfa, "a"));
+        assert!(!matches(&nfa, "]"));
+        assert!(!matches(&nfa, ""));
+        assert!(!matches(&parse("[]").unwrap(), ""));
+        assert!(matches(&parse("[^]").unwrap(), "a"));
+        assert!(matches(&parse("\\[a]").unwrap(), "[a]"));
+        let nfa = parse_ci("[^a]").unwrap();
+        assert!(!matches(&nfa, "A"));
+        assert!(matches(&nfa, "b"));
+        assert_eq!(parse("a[bc").unwrap_err().offset, 1);
+        assert_eq!(parse("[b\\").unwrap_err().message, "unmatched '['");
+    }
+
+    #[test]
+    pub fn test_parse_class_ranges() {
+        let nfa = parse("[a-cx]").unwrap();
+        for input in ["a", "b", "c", "x"] {
+            assert!(matches(&nfa, input), "{:?}", input);
+        }
+        assert!(!matches(&nfa, "-"));
+        assert!(!matches(&nfa, "d"));
+        let nfa = parse("[^0-9\\-]").unwrap();
+        assert!(matches(&nfa, "a"));
+        assert!(!matches(&nfa, "5"));
+        assert!(!matches(&nfa, "-"));
+        let nfa = parse_ci("[a-c]").unwrap();
+        assert!(matches(&nfa, "B"));
+        assert!(!parse_ci("[^a-c]").unwrap().is_match("B"));
+        assert!(matches(&parse("[\\--\\/]").unwrap(), "."));
+        assert!(matches(&parse("[a\\-c]").unwrap(), "-"));
+        assert!(!matches(&parse("[a\\-c]").unwrap(), "b"));
+    }
+
+    #[test]
+    pub fn test_parse_class_dash_errors() {
+        // an unescaped '-' has to separate the two ends of a range
+        for (pattern, offset, message) in [
+            ("[-a]", 1, "'-' outside a range"),
+            ("[a-c-e]", 4, "'-' outside a range"),
+            ("[a--]", 3, "'-' outside a range"),
+            ("[a-]", 2, "unfinished range"),
+            ("[c-a]", 2, "range out of order"),
+            ("[a-", 0, "unmatched '['"),
+        ] {
+            assert_eq!(
+                parse(pattern).unwrap_err(),
+                ParseError { offset, message },
+                "{:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_parse_classes_agree_with_regex_crate() {
+        let patterns = ["[a-c]*", "[^b]a", "([ab]|[^a-b])*c", "[\\-a]b", "[^\\]]"];
+        let inputs = ["", "a", "ab", "ba", "cc", "abc", "-b", "]", "dc", "aaac"];
+        for pattern in patterns {
+            let nfa = parse(pattern).unwrap();
+            let reference = regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap();
+            for input in inputs {
+                assert_eq!(
+                    matches(&nfa, input),
+                    reference.is_match(input),
+                    "{:?} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+
+    fn random_pattern(rng: &mut Lcg, depth: u32) -> String {
+        let choice = if depth == 0 {
+            rng.below(3)
+        } else {
+            rng.below(7)
+        };
+        match choice {
+            0 => String::from("a"),
+            1 => String::from("b"),
+            2 => String::from("."),
+            3 | 4 => format!(
+                "{}{}",
+                random_pattern(rng, depth - 1),
+                random_pattern(rng, depth - 1)
+            ),
+            5 => format!(
+                "({}|{})",
+                random_pattern(rng, depth - 1),
+                random_pattern(rng, depth - 1)
+            ),
+            _ => format!("({})*", random_pattern(rng, depth - 1)),
+        }
+    }
+
+    #[test]
+    pub fn test_agrees_with_regex_crate() {
+        let mut rng = Lcg(0x5eed);
+        let mut inputs = vec![String::new()];
+        for _ in 0..4 {
+            let longer: Vec<String> = inputs
+                .iter()
+                .flat_map(|s| ['a', 'b', 'c'].map(|ch| format!("{}{}", s, ch)))
+                .collect();
+            inputs.extend(longer);
+        }
+        inputs.sort();
+        inputs.dedup();
+        for _ in 0..200 {
+            let pattern = random_pattern(&mut rng, 4);
+            let nfa = parse(&pattern).unwrap();
+            let reference = regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap();
+            for input in inputs.iter() {
+                assert_eq!(
+                    matches(&nfa, input),
+                    reference.is_match(input),
+                    "{:?} on {:?}",
+                    pattern,
+                    input
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::dfa::DfaTable;
+use crate::nfa::NFA;
+use crate::parser::{parse, ParseError};
+use std::sync::OnceLock;
+
+/// A parsed pattern, the usual entry point to the crate.
+///
+/// Whole-string matching goes through a DFA table that is only built, once,
+/// on the first call to `is_match`; searching uses the NFA directly.
+///
+/// A `Regex` is `Send` and `Sync`: the table sits in a `OnceLock`, so one
+/// instance can be shared, e.g. behind an `Arc`, by threads matching
+/// concurrently, and the table is still built only once.
+///
+/// ```
+/// use reg::Regex;
+///
+/// # fn main() -> Result<(), reg::parser::ParseError> {
+/// assert!(Regex::new("(ab)*c")?.is_match("ababc"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Regex {
+    nfa: NFA,
+    // `None` if the automaton has predicate edges
+    table: OnceLock<Option<DfaTable>>,
+}
+
+impl Regex {
+    /// Parses `pattern` with the syntax described in `parser::parse`.
+    pub fn new(pattern: &str) -> Result<Regex, ParseError> {
+        Ok(Regex::from(parse(pattern)?))
+    }
+
+    /// Whether the whole of `s` matches. Automata with predicate edges can't
+    /// be turned into a table and are simulated instead.
+    pub fn is_match(&self, s: &str) -> bool {
+        let table = self.table.get_or_init(|| {
+            (!self.nfa.has_predicates()).then(|| self.nfa.to_dfa().minimize().to_table())
+        });
+        match table {
+            Some(table) => table.is_match(s),
+            None => self.nfa.is_match(s),
+        }
+    }
+
+    /// See `NFA::find`.
+    pub fn find(&self, s: &str) -> Option<(usize, usize)> {
+        self.nfa.find(s)
+    }
+
+    /// See `NFA::find_all`.
+    pub fn find_all(&self, s: &str) -> Vec<(usize, usize)> {
+        self.nfa.find_all(s)
+    }
+
+    /// See `NFA::replace_all`.
+    pub fn replace_all(&self, s: &str, replacement: &str) -> String {
+        self.nfa.replace_all(s, replacement)
+    }
+
+    /// See `NFA::split`.
+    pub fn split<'a>(&self, s: &'a str) -> Vec<&'a str> {
+        self.nfa.split(s)
+    }
+
+    pub fn as_nfa(&self) -> &NFA {
+        &self.nfa
+    }
+}
+
+impl From<NFA> for Regex {
+    fn from(nfa: NFA) -> Regex {
+        Regex {
+            nfa,
+            table: OnceLock::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nfa::literal;
+    use crate::regex::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    pub fn test_regex() {
+        let re = Regex::new("(ab)*c").unwrap();
+        assert!(re.is_match("ababc"));
+        assert!(re.is_match("c"));
+        assert!(!re.is_match("abab"));
+        assert_eq!(re.find("xxabcx"), Some((2, 5)));
+        assert_eq!(re.find_all("cabc"), vec![(0, 1), (1, 4)]);
+        assert!(Regex::new("a(").is_err());
+        assert!(Regex::from(literal("日本")).is_match("日本"));
+    }
+
+    #[test]
+    pub fn test_regex_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Regex>();
+        assert_send_sync::<crate::compiled::CompiledNFA>();
+        assert_send_sync::<crate::dfa::DfaTable>();
+    }
+
+    #[test]
+    pub fn test_regex_shared_across_threads() {
+        let re = Arc::new(Regex::new("(ab)*c").unwrap());
+        let inputs = ["ababc", "c", "abab", "", "abc", "abcc"];
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let re = Arc::clone(&re);
+                thread::spawn(move || inputs.map(|input| re.is_match(input)))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(
+                handle.join().unwrap(),
+                [true, true, false, false, true, false]
+            );
+        }
+    }
+}